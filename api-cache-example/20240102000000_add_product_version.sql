@@ -0,0 +1,3 @@
+-- Row version for optimistic concurrency: every UPDATE bumps it, and a
+-- PATCH only applies if the version it was based on is still current.
+ALTER TABLE products ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
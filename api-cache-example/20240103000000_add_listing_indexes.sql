@@ -0,0 +1,7 @@
+-- Keyset pagination walks (category, sort column, id); one index per sort
+-- column lets every page, however deep, start with an index seek.
+CREATE INDEX IF NOT EXISTS idx_products_category_name_id  ON products(category, name, id);
+CREATE INDEX IF NOT EXISTS idx_products_category_price_id ON products(category, price_cents, id);
+
+-- Superseded by idx_products_category_name_id.
+DROP INDEX IF EXISTS idx_products_category;
@@ -0,0 +1,15 @@
+-- Transactional outbox: every product mutation inserts its change event here
+-- in the same transaction, and the relay publishes unsent rows in id order.
+CREATE TABLE IF NOT EXISTS product_outbox (
+    id           BIGSERIAL   PRIMARY KEY,
+    product_id   UUID        NOT NULL,
+    event_type   TEXT        NOT NULL,
+    payload      JSONB       NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at      TIMESTAMPTZ,
+    attempts     INTEGER     NOT NULL DEFAULT 0,
+    last_error   TEXT
+);
+
+-- The relay only ever scans unsent events.
+CREATE INDEX IF NOT EXISTS idx_product_outbox_unsent ON product_outbox(id) WHERE sent_at IS NULL;
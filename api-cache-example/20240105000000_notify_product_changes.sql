@@ -0,0 +1,48 @@
+-- Announce every committed change to products on the `product_changes`
+-- channel, including writes that bypass the API (psql, other services).
+-- The payload is a cache invalidation in the same JSON shape as the Redis
+-- invalidation bus: the product plus the categories whose pages it may now
+-- belong on (same rules as the API's own writes).
+CREATE OR REPLACE FUNCTION notify_product_change() RETURNS trigger AS $$
+DECLARE
+    categories TEXT[];
+BEGIN
+    IF TG_OP = 'TRUNCATE' THEN
+        PERFORM pg_notify('product_changes', json_build_object('kind', 'all')::text);
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        categories := ARRAY[NEW.category];
+    ELSIF TG_OP = 'DELETE' THEN
+        categories := ARRAY[]::TEXT[];
+    ELSIF OLD.category IS DISTINCT FROM NEW.category THEN
+        categories := ARRAY[OLD.category, NEW.category];
+    ELSIF (OLD.name, OLD.price_cents, OLD.stock) IS DISTINCT FROM (NEW.name, NEW.price_cents, NEW.stock) THEN
+        categories := ARRAY[NEW.category];
+    ELSE
+        categories := ARRAY[]::TEXT[];
+    END IF;
+
+    -- NOTIFY is delivered on commit, and not at all on rollback.
+    PERFORM pg_notify(
+        'product_changes',
+        json_build_object(
+            'kind', 'product',
+            'id', CASE WHEN TG_OP = 'DELETE' THEN OLD.id ELSE NEW.id END,
+            'categories', categories
+        )::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS products_notify_change ON products;
+CREATE TRIGGER products_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON products
+    FOR EACH ROW EXECUTE FUNCTION notify_product_change();
+
+DROP TRIGGER IF EXISTS products_notify_truncate ON products;
+CREATE TRIGGER products_notify_truncate
+    AFTER TRUNCATE ON products
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_product_change();
@@ -0,0 +1,254 @@
+/// admin.rs — Operator routes for inspecting and managing the cache
+///
+/// Design guarantees:
+///
+/// 1. OFF UNLESS CONFIGURED
+///    - The routes are only mounted when an admin token is configured
+///      (`ADMIN_TOKEN`). Without one they do not exist (404), so a forgotten
+///      setting never leaves them open.
+///
+/// 2. TOKEN ON EVERY REQUEST
+///    - Every admin request must carry `Authorization: Bearer <token>`. The
+///      comparison takes the same time however much of the token matches.
+///
+/// 3. SAME INVALIDATION PATH AS WRITES
+///    - Evictions go through L2, L1 and the invalidation bus exactly like a
+///      write does, so evicting on one replica evicts on all of them.
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    cache::{PageEntry, ProductEntry},
+    error::AppError,
+    handlers::AppState,
+};
+
+/// Warm the top 10 categories, first page only, unless asked otherwise.
+const DEFAULT_WARM_CATEGORIES: u32 = 10;
+const DEFAULT_WARM_PAGES: u32 = 1;
+
+/// Upper bounds on one warm request, so it cannot load the whole table.
+const MAX_WARM_CATEGORIES: u32 = 100;
+const MAX_WARM_PAGES: u32 = 20;
+
+// ---------------------------------------------------------------------------
+// Router + auth
+// ---------------------------------------------------------------------------
+
+/// Admin routes, to be nested under `/admin`. `token` is required on every
+/// request.
+pub fn router(token: String) -> Router<AppState> {
+    let token: Arc<str> = token.into();
+    Router::new()
+        .route("/cache/products/:id",         get(inspect_product).delete(evict_product))
+        .route("/cache/categories/:category", get(inspect_category).delete(evict_category))
+        .route("/cache/warm",                 post(warm))
+        .route("/cache/flush",                post(flush))
+        .route_layer(middleware::from_fn_with_state(token, require_token))
+}
+
+async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(AppError::Unauthorized),
+    }
+}
+
+/// Byte comparison whose running time does not depend on where the inputs
+/// first differ. (The length is not secret.)
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// ---------------------------------------------------------------------------
+// GET / DELETE /admin/cache/products/:id
+// ---------------------------------------------------------------------------
+
+async fn inspect_product(State(state): State<AppState>, Path(id): Path<Uuid>) -> Json<ProductEntry> {
+    Json(state.cache.inspect_product(id).await)
+}
+
+async fn evict_product(State(state): State<AppState>, Path(id): Path<Uuid>) -> StatusCode {
+    state.cache.evict_product(id).await;
+    StatusCode::NO_CONTENT
+}
+
+// ---------------------------------------------------------------------------
+// GET / DELETE /admin/cache/categories/:category
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize)]
+struct CategoryEntry {
+    category: String,
+    pages: Vec<PageEntry>,
+}
+
+async fn inspect_category(
+    State(state): State<AppState>,
+    Path(category): Path<String>,
+) -> Json<CategoryEntry> {
+    let pages = state.cache.inspect_category(&category).await;
+    Json(CategoryEntry { category, pages })
+}
+
+async fn evict_category(State(state): State<AppState>, Path(category): Path<String>) -> StatusCode {
+    state.cache.evict_category(&category).await;
+    StatusCode::NO_CONTENT
+}
+
+// ---------------------------------------------------------------------------
+// POST /admin/cache/warm
+// ---------------------------------------------------------------------------
+
+#[derive(Default, Deserialize)]
+struct WarmRequest {
+    /// How many of the largest categories to warm.
+    categories: Option<u32>,
+    /// How many pages of each.
+    pages: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct WarmStarted {
+    categories: Vec<String>,
+    pages: u32,
+}
+
+/// Starts warming in the background and returns at once (202) with the
+/// categories it picked. An empty body means the defaults; any other body
+/// must parse, so a malformed one is a 400 rather than a default warm.
+async fn warm(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<WarmStarted>), AppError> {
+    let body: WarmRequest = if body.is_empty() {
+        WarmRequest::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| AppError::BadRequest(format!("invalid warm request: {e}")))?
+    };
+    let limit = body.categories.unwrap_or(DEFAULT_WARM_CATEGORIES);
+    let pages = body.pages.unwrap_or(DEFAULT_WARM_PAGES);
+    if !(1..=MAX_WARM_CATEGORIES).contains(&limit) || !(1..=MAX_WARM_PAGES).contains(&pages) {
+        return Err(AppError::BadRequest(format!(
+            "categories must be 1..={MAX_WARM_CATEGORIES} and pages 1..={MAX_WARM_PAGES}"
+        )));
+    }
+
+    let categories = state.cache.top_categories(limit).await?;
+    tracing::info!(?categories, pages, "warming cache");
+    state.cache.spawn_warm(categories.clone(), pages);
+
+    Ok((StatusCode::ACCEPTED, Json(WarmStarted { categories, pages })))
+}
+
+// ---------------------------------------------------------------------------
+// POST /admin/cache/flush
+// ---------------------------------------------------------------------------
+
+async fn flush(State(state): State<AppState>) -> StatusCode {
+    tracing::warn!("flushing all caches on admin request");
+    state.cache.invalidate_all().await;
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request as HttpRequest};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const TOKEN: &str = "s3cret";
+
+    async fn send(method: &str, uri: &str, authorization: Option<&str>, body: &str) -> StatusCode {
+        let app = router(TOKEN.to_owned()).with_state(AppState::offline());
+        let mut request = HttpRequest::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(value) = authorization {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        let response = app.oneshot(request.body(Body::from(body.to_owned())).unwrap()).await.unwrap();
+        response.status()
+    }
+
+    #[test]
+    fn constant_time_eq_compares_bytes() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+        assert!(!constant_time_eq(b"token", b""));
+    }
+
+    #[tokio::test]
+    async fn missing_token_is_unauthorized() {
+        assert_eq!(send("POST", "/cache/flush", None, "").await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn wrong_scheme_is_unauthorized() {
+        let basic = format!("Basic {TOKEN}");
+        assert_eq!(send("POST", "/cache/flush", Some(&basic), "").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send("POST", "/cache/flush", Some(TOKEN), "").await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn wrong_token_is_unauthorized() {
+        let status = send("POST", "/cache/flush", Some("Bearer s3cre"), "").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn correct_token_passes_through() {
+        let bearer = format!("Bearer {TOKEN}");
+        assert_eq!(send("POST", "/cache/flush", Some(&bearer), "").await, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn warm_rejects_out_of_range_requests() {
+        let bearer = format!("Bearer {TOKEN}");
+        for body in [
+            r#"{"categories": 0}"#,
+            r#"{"categories": 101}"#,
+            r#"{"pages": 0}"#,
+            r#"{"pages": 21}"#,
+        ] {
+            let status = send("POST", "/cache/warm", Some(&bearer), body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "body {body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn warm_rejects_malformed_bodies() {
+        let bearer = format!("Bearer {TOKEN}");
+        for body in [r#"{"categories": "ten"}"#, r#"{"pages": -1}"#, "{", "[]"] {
+            let status = send("POST", "/cache/warm", Some(&bearer), body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "body {body}");
+        }
+    }
+}
@@ -0,0 +1,605 @@
+/// bulk.rs — Streaming product import and export (NDJSON / CSV)
+///
+/// Design guarantees:
+///
+/// 1. STREAMING BOTH WAYS
+///    - Imports are read line by line from the request body and exports are
+///      written batch by batch, so neither holds the whole data set in memory.
+///
+/// 2. BATCHED TRANSACTIONS, PER-ROW ERRORS
+///    - Rows are upserted `IMPORT_BATCH_SIZE` at a time in one transaction.
+///      Each row runs under its own savepoint, so a bad row (parse error,
+///      constraint violation) is reported with its line number and the rest
+///      of the batch still commits. A row's outbox event is written under
+///      the same savepoint as the row.
+///
+/// 3. ONE INVALIDATION PER BATCH
+///    - After each batch commits, the cache is invalidated once for all of
+///      its rows (`invalidate_products`): one L2 pass and one bus message,
+///      not one per row. Invalidation still happens strictly after commit.
+///
+/// CSV is RFC 4180 with a header row, one record per line: quoted fields may
+/// contain commas and doubled quotes, but not line breaks.
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, TryStreamExt};
+use http_body_util::LengthLimitError;
+use serde::{Deserialize, Serialize};
+use sqlx::Connection;
+use tokio::io::AsyncBufReadExt;
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::{
+    cache::ProductChange,
+    db::DbPool,
+    error::AppError,
+    handlers::AppState,
+    model::Product,
+    outbox::{self, EventKind},
+};
+
+/// Rows per import transaction (and per cache invalidation).
+const IMPORT_BATCH_SIZE: usize = 500;
+/// Rows per export query.
+const EXPORT_BATCH_SIZE: i64 = 1000;
+/// Row errors listed in the import report; later ones are only counted.
+const MAX_REPORTED_ERRORS: usize = 1000;
+
+/// Columns written by the CSV export. Import reads the same header and
+/// ignores columns it does not use (`updated_at`, `version`).
+const CSV_COLUMNS: [&str; 7] = ["id", "name", "category", "price_cents", "stock", "updated_at", "version"];
+
+// ---------------------------------------------------------------------------
+// Formats
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Ndjson,
+    Csv,
+}
+
+impl Format {
+    fn from_content_type(headers: &HeaderMap) -> Result<Self, AppError> {
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime {
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => Ok(Format::Ndjson),
+            "text/csv" => Ok(Format::Csv),
+            _ => Err(AppError::BadRequest(
+                "Content-Type must be application/x-ndjson or text/csv".into(),
+            )),
+        }
+    }
+
+    fn from_query(format: Option<&str>) -> Result<Self, AppError> {
+        match format.unwrap_or("ndjson") {
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            other => Err(AppError::BadRequest(format!("unknown format {other:?}; expected ndjson or csv"))),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Ndjson => "application/x-ndjson",
+            Format::Csv => "text/csv",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// POST /products/import
+// ---------------------------------------------------------------------------
+
+/// One imported row. With an `id` it is an upsert; without, an insert.
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    #[serde(default)]
+    id: Option<Uuid>,
+    name: String,
+    category: String,
+    price_cents: i64,
+    stock: i32,
+}
+
+impl ImportRow {
+    fn validate(self) -> Result<Self, String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".into());
+        }
+        if self.price_cents < 0 {
+            return Err("price_cents must not be negative".into());
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RowError {
+    /// 1-based line number in the request body (the CSV header is line 1).
+    line: usize,
+    error: String,
+}
+
+/// Outcome of an import, returned once the whole body has been read.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    inserted: u64,
+    updated: u64,
+    failed: u64,
+    errors: Vec<RowError>,
+    /// More rows failed than are listed in `errors`.
+    errors_truncated: bool,
+}
+
+impl ImportReport {
+    fn fail(&mut self, line: usize, error: impl Into<String>) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(RowError { line, error: error.into() });
+        } else {
+            self.errors_truncated = true;
+        }
+    }
+}
+
+/// Stream NDJSON or CSV rows into the table, `IMPORT_BATCH_SIZE` per
+/// transaction. Batches already committed stay committed if the body is cut
+/// off part-way.
+pub async fn import_products(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ImportReport>, AppError> {
+    let format = Format::from_content_type(&headers)?;
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let mut lines = reader.lines();
+
+    let mut report = ImportReport::default();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut csv_columns: Option<HashMap<String, usize>> = None;
+    let mut line_no = 0;
+
+    while let Some(line) = lines.next_line().await.map_err(read_error)? {
+        line_no += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row = match format {
+            Format::Ndjson => serde_json::from_str::<ImportRow>(&line).map_err(|e| e.to_string()),
+            Format::Csv => match &csv_columns {
+                None => {
+                    csv_columns = Some(parse_csv_header(&line)?);
+                    continue;
+                }
+                Some(columns) => parse_csv_row(columns, &line),
+            },
+        };
+        match row.and_then(ImportRow::validate) {
+            Ok(row) => batch.push((line_no, row)),
+            Err(e) => report.fail(line_no, e),
+        }
+
+        if batch.len() >= IMPORT_BATCH_SIZE {
+            apply_batch(&state, std::mem::take(&mut batch), &mut report).await?;
+        }
+    }
+    apply_batch(&state, batch, &mut report).await?;
+
+    tracing::info!(
+        inserted = report.inserted,
+        updated = report.updated,
+        failed = report.failed,
+        "bulk import finished"
+    );
+    Ok(Json(report))
+}
+
+/// A failed body read: the import size limit (413), or a broken upload.
+fn read_error(e: std::io::Error) -> AppError {
+    // The limit error sits under the io and axum error wrappers.
+    let mut source = e.get_ref().map(|inner| inner as &(dyn std::error::Error + 'static));
+    while let Some(error) = source {
+        if error.is::<LengthLimitError>() {
+            return AppError::PayloadTooLarge;
+        }
+        source = error.source();
+    }
+    AppError::BadRequest(format!("failed to read body: {e}"))
+}
+
+/// Upsert one batch in a single transaction, then invalidate once.
+async fn apply_batch(
+    state: &AppState,
+    batch: Vec<(usize, ImportRow)>,
+    report: &mut ImportReport,
+) -> Result<(), AppError> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = state.db.begin().await?;
+
+    // Rows being replaced, locked for the rest of the batch. Their old
+    // category tells the cache which pages a category move affects.
+    let ids: Vec<Uuid> = batch.iter().filter_map(|(_, row)| row.id).collect();
+    let before: HashMap<Uuid, Product> =
+        sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = ANY($1) FOR UPDATE")
+            .bind(&ids)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|product| (product.id, product))
+            .collect();
+
+    let mut written = Vec::with_capacity(batch.len());
+    let mut seen = HashSet::new();
+    for (line, row) in batch {
+        let mut savepoint = tx.begin().await?;
+        let result = sqlx::query_as::<_, Product>(
+            "INSERT INTO products (id, name, category, price_cents, stock, updated_at)
+             VALUES (COALESCE($1, gen_random_uuid()), $2, $3, $4, $5, NOW())
+             ON CONFLICT (id) DO UPDATE
+                SET name        = EXCLUDED.name,
+                    category    = EXCLUDED.category,
+                    price_cents = EXCLUDED.price_cents,
+                    stock       = EXCLUDED.stock,
+                    updated_at  = NOW(),
+                    version     = products.version + 1
+             RETURNING *",
+        )
+        .bind(row.id)
+        .bind(&row.name)
+        .bind(&row.category)
+        .bind(row.price_cents)
+        .bind(row.stock)
+        .fetch_one(&mut *savepoint)
+        .await;
+
+        // The row's change event shares its savepoint: both or neither.
+        let result = match result {
+            Ok(product) => {
+                // A repeated id within the batch is an update the second time.
+                let kind = if before.contains_key(&product.id) || seen.contains(&product.id) {
+                    EventKind::Updated
+                } else {
+                    EventKind::Created
+                };
+                outbox::record(&mut savepoint, kind, &product).await.map(|()| (kind, product))
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok((kind, product)) => {
+                savepoint.commit().await?;
+                match kind {
+                    EventKind::Created => report.inserted += 1,
+                    _ => report.updated += 1,
+                }
+                seen.insert(product.id);
+                written.push(product);
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                report.fail(line, e.to_string());
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    // REORDER SAFETY: invalidate AFTER the batch commits, once for all rows.
+    let changes: Vec<ProductChange> = written
+        .iter()
+        .map(|after| match before.get(&after.id) {
+            Some(before) => ProductChange::Updated { before, after },
+            None => ProductChange::Created(after),
+        })
+        .collect();
+    state.cache.invalidate_products(&changes).await;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// GET /products/export?format=csv&category=widgets
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+    pub category: Option<String>,
+}
+
+/// Where the export stream has got to.
+struct ExportCursor {
+    db: DbPool,
+    format: Format,
+    category: Option<String>,
+    after: Option<Uuid>,
+    /// CSV header, until it has been sent.
+    header: Option<String>,
+}
+
+/// Stream every product (or one category) in `id` order, reading
+/// `EXPORT_BATCH_SIZE` rows at a time straight from the DB — not the cache,
+/// which only ever holds a fraction of the table.
+///
+/// Batches are separate queries, so a row written during the export appears
+/// in whichever state it had when its batch was read. A DB error part-way
+/// aborts the response, and the client sees a truncated body.
+pub async fn export_products(
+    State(state): State<AppState>,
+    Query(q): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let format = Format::from_query(q.format.as_deref())?;
+    let cursor = ExportCursor {
+        db: state.db.clone(),
+        format,
+        category: q.category,
+        after: None,
+        header: matches!(format, Format::Csv).then(|| format!("{}\n", CSV_COLUMNS.join(","))),
+    };
+
+    let chunks = stream::try_unfold(cursor, |mut cursor| async move {
+        let products = sqlx::query_as::<_, Product>(
+            "SELECT id, name, category, price_cents, stock, updated_at, version
+               FROM products
+              WHERE ($1::text IS NULL OR category = $1)
+                AND ($2::uuid IS NULL OR id > $2)
+              ORDER BY id
+              LIMIT $3",
+        )
+        .bind(&cursor.category)
+        .bind(cursor.after)
+        .bind(EXPORT_BATCH_SIZE)
+        .fetch_all(&cursor.db)
+        .await?;
+
+        let mut chunk = cursor.header.take().unwrap_or_default();
+        let Some(last) = products.last() else {
+            // Even an empty CSV export carries its header.
+            return Ok::<_, sqlx::Error>((!chunk.is_empty()).then(|| (Bytes::from(chunk), cursor)));
+        };
+        cursor.after = Some(last.id);
+
+        for product in &products {
+            match cursor.format {
+                Format::Ndjson => {
+                    // A plain struct of strings and numbers always serialises.
+                    chunk.push_str(&serde_json::to_string(product).unwrap_or_default());
+                    chunk.push('\n');
+                }
+                Format::Csv => push_csv_row(&mut chunk, product),
+            }
+        }
+        Ok(Some((Bytes::from(chunk), cursor)))
+    });
+
+    Ok(([(header::CONTENT_TYPE, format.content_type())], Body::from_stream(chunks)).into_response())
+}
+
+// ---------------------------------------------------------------------------
+// CSV
+// ---------------------------------------------------------------------------
+
+/// Column name → position. `name`, `category`, `price_cents` and `stock` are
+/// required; `id` is optional.
+fn parse_csv_header(line: &str) -> Result<HashMap<String, usize>, AppError> {
+    let fields = split_csv_line(line).map_err(|e| AppError::BadRequest(format!("CSV header: {e}")))?;
+    let columns: HashMap<String, usize> = fields
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name.trim().to_owned(), i))
+        .collect();
+    for required in ["name", "category", "price_cents", "stock"] {
+        if !columns.contains_key(required) {
+            return Err(AppError::BadRequest(format!("CSV header is missing column {required:?}")));
+        }
+    }
+    Ok(columns)
+}
+
+fn parse_csv_row(columns: &HashMap<String, usize>, line: &str) -> Result<ImportRow, String> {
+    let fields = split_csv_line(line)?;
+    let field = |name: &str| -> Option<&str> {
+        let value = fields.get(*columns.get(name)?)?.as_str();
+        (!value.is_empty()).then_some(value)
+    };
+    let required = |name: &str| field(name).ok_or_else(|| format!("missing {name}"));
+
+    Ok(ImportRow {
+        id: field("id").map(str::parse).transpose().map_err(|e| format!("id: {e}"))?,
+        name: required("name")?.to_owned(),
+        category: required("category")?.to_owned(),
+        price_cents: required("price_cents")?.parse().map_err(|e| format!("price_cents: {e}"))?,
+        stock: required("stock")?.parse().map_err(|e| format!("stock: {e}"))?,
+    })
+}
+
+/// Split one CSV record. Quoted fields may contain commas and `""`.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".into());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn push_csv_row(out: &mut String, product: &Product) {
+    let fields = [
+        product.id.to_string(),
+        csv_escape(&product.name),
+        csv_escape(&product.category),
+        product.price_cents.to_string(),
+        product.stock.to_string(),
+        product.updated_at.to_rfc3339(),
+        product.version.to_string(),
+    ];
+    out.push_str(&fields.join(","));
+    out.push('\n');
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn header() -> HashMap<String, usize> {
+        parse_csv_header("name,category,price_cents,stock,id").unwrap()
+    }
+
+    #[test]
+    fn split_plain_fields() {
+        assert_eq!(split_csv_line("a,b,,c").unwrap(), ["a", "b", "", "c"]);
+        assert_eq!(split_csv_line("").unwrap(), [""]);
+    }
+
+    #[test]
+    fn split_quoted_commas() {
+        assert_eq!(split_csv_line(r#""a,b",c"#).unwrap(), ["a,b", "c"]);
+    }
+
+    #[test]
+    fn split_escaped_quotes() {
+        assert_eq!(split_csv_line(r#""say ""hi""",x"#).unwrap(), [r#"say "hi""#, "x"]);
+        assert_eq!(split_csv_line(r#""""""#).unwrap(), [r#"""#]);
+    }
+
+    #[test]
+    fn split_unterminated_quote() {
+        assert!(split_csv_line(r#""open,field"#).is_err());
+    }
+
+    #[test]
+    fn header_columns_in_any_order() {
+        let columns = parse_csv_header(" stock , name,category,price_cents").unwrap();
+        assert_eq!(columns["stock"], 0);
+        assert_eq!(columns["price_cents"], 3);
+        assert!(!columns.contains_key("id"));
+    }
+
+    #[test]
+    fn header_missing_required_column() {
+        let Err(AppError::BadRequest(message)) = parse_csv_header("name,category,stock") else {
+            panic!("a header without price_cents must be rejected");
+        };
+        assert!(message.contains("price_cents"));
+    }
+
+    #[test]
+    fn row_with_and_without_id() {
+        let row = parse_csv_row(&header(), "Widget,tools,250,3,").unwrap();
+        assert_eq!((row.id, row.name.as_str(), row.price_cents, row.stock), (None, "Widget", 250, 3));
+
+        let id = Uuid::new_v4();
+        let row = parse_csv_row(&header(), &format!("Widget,tools,250,3,{id}")).unwrap();
+        assert_eq!(row.id, Some(id));
+    }
+
+    #[test]
+    fn row_with_empty_required_field() {
+        assert_eq!(parse_csv_row(&header(), "Widget,tools,,3,").unwrap_err(), "missing price_cents");
+        assert_eq!(parse_csv_row(&header(), "Widget,tools,250").unwrap_err(), "missing stock");
+    }
+
+    #[test]
+    fn row_with_invalid_numbers() {
+        let error = parse_csv_row(&header(), "Widget,tools,2.50,3,").unwrap_err();
+        assert!(error.starts_with("price_cents:"), "{error}");
+        let error = parse_csv_row(&header(), "Widget,tools,250,lots,").unwrap_err();
+        assert!(error.starts_with("stock:"), "{error}");
+        let error = parse_csv_row(&header(), "Widget,tools,250,3,not-a-uuid").unwrap_err();
+        assert!(error.starts_with("id:"), "{error}");
+    }
+
+    #[test]
+    fn escape_round_trips_through_split() {
+        for value in ["plain", "", "a,b", r#"say "hi""#, r#"""#, r#"x","y"#] {
+            let line = format!("{},{}", csv_escape(value), csv_escape("next"));
+            assert_eq!(split_csv_line(&line).unwrap(), [value, "next"], "{value:?}");
+        }
+    }
+
+    #[test]
+    fn exported_row_imports_back() {
+        let product = Product {
+            id: Uuid::new_v4(),
+            name: r#"Widget, "deluxe""#.into(),
+            category: "tools".into(),
+            price_cents: 1999,
+            stock: 7,
+            updated_at: Utc::now(),
+            version: 3,
+        };
+        let mut csv = String::new();
+        push_csv_row(&mut csv, &product);
+
+        let columns = parse_csv_header(&CSV_COLUMNS.join(",")).unwrap();
+        let row = parse_csv_row(&columns, csv.trim_end()).unwrap();
+        assert_eq!(row.id, Some(product.id));
+        assert_eq!(row.name, product.name);
+        assert_eq!(row.category, product.category);
+        assert_eq!((row.price_cents, row.stock), (product.price_cents, product.stock));
+    }
+
+    #[test]
+    fn report_truncates_listed_errors() {
+        let mut report = ImportReport::default();
+        for line in 0..MAX_REPORTED_ERRORS + 5 {
+            report.fail(line + 2, "bad row");
+        }
+        assert_eq!(report.failed, (MAX_REPORTED_ERRORS + 5) as u64);
+        assert_eq!(report.errors.len(), MAX_REPORTED_ERRORS);
+        assert!(report.errors_truncated);
+        assert_eq!(report.errors.last().unwrap().line, MAX_REPORTED_ERRORS + 1);
+    }
+
+    #[test]
+    fn report_under_the_cap_is_complete() {
+        let mut report = ImportReport::default();
+        report.fail(2, "bad row");
+        assert_eq!(report.failed, 1);
+        assert!(!report.errors_truncated);
+    }
+}
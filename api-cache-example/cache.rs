@@ -3,10 +3,11 @@
 /// Design guarantees:
 ///
 /// 1. OUT-OF-MEMORY SAFETY
-///    - `moka` enforces a hard max_capacity (entry count) and optional weigher
-///      (byte-based size). When the cache is full it evicts via TinyLFU policy —
-///      high-frequency entries survive; rarely-used ones are dropped before the
-///      process ever approaches OOM.
+///    - `moka` enforces a hard byte budget per cache: a weigher estimates each
+///      entry's heap footprint, so one huge category page costs what it
+///      weighs rather than counting as one entry. When the cache is full it
+///      evicts via TinyLFU policy — high-frequency entries survive;
+///      rarely-used ones are dropped before the process ever approaches OOM.
 ///    - TTL (time-to-live) + TTI (time-to-idle) bound total memory across time:
 ///      stale entries are automatically removed even if capacity is not exceeded.
 ///
@@ -29,15 +30,75 @@
 ///    - Write-through invalidation uses `.invalidate()` (async, waits for
 ///      pending writes to finish) so a stale read cannot be served after a
 ///      committed mutation.
-use std::{sync::Arc, time::Duration};
+///
+/// 5. TWO TIERS
+///    - With an `L2Cache` attached, a miss goes L1 (moka) → L2 (Redis) → DB.
+///      The L2 lookup and the DB fetch both run *inside* the `try_get_with`
+///      initialiser, so coalescing still holds per key: one L2 read, and at
+///      most one DB query, per key per replica.
+///    - Invalidation clears L2 *before* L1, so an L1 reload racing the
+///      invalidation cannot pick the old value back up from L2.
+///    - A DB result is written back to L2 only if nothing it depends on was
+///      invalidated since the L2 miss (see `l2_cache.rs`), so a load that
+///      read the DB before a write committed cannot put the old value back
+///      into L2 after that write cleared it.
+///
+/// 6. CROSS-INSTANCE INVALIDATION
+///    - With an `InvalidationBus` attached, every local invalidation is also
+///      published so other replicas drop the same L1 entries. Remote
+///      invalidations only touch L1 — the origin has already cleared L2.
+///    - Writes seen through Postgres NOTIFY (`db_listener.rs`) reach every
+///      replica directly, so each clears L2 and its own L1 and publishes
+///      nothing.
+///
+/// 7. FINE-GRAINED PAGE INVALIDATION
+///    - A `PageIndex` tracks which cached listing pages contain which
+///      products. Pages are cut by keyset cursor, so a delete drops only the
+///      pages holding the product. A write that can bring a product onto a
+///      page that does not list it yet (create, or an update to a field pages
+///      sort or filter on) drops every page of each category involved — both
+///      old and new on a move.
+///    - L2 keeps the same index in Redis (page keys per product and per
+///      category), since no single replica's `PageIndex` knows the L2 pages
+///      other replicas wrote. A write drops the same set of pages from L2.
+///
+/// 8. STALE-WHILE-REVALIDATE / REFRESH-AHEAD
+///    - With a soft TTL set for a cache, a hit older than the soft TTL is
+///      returned at once and one background task per key reloads it from the
+///      DB (see `refresh.rs`). `time_to_live` stays the hard cap: past it the
+///      entry is gone and the next reader waits for the DB as usual.
+///
+/// 9. NEGATIVE CACHING
+///    - `try_get_with` never caches errors, so a lookup of a nonexistent ID
+///      would reach Postgres every time. `NotFound` results are remembered in
+///      a separate, bounded cache with a short TTL of its own; any write to
+///      that ID (notably a create) removes the entry. The short TTL also caps
+///      the damage if a lookup races a create and records the miss late.
+use std::{
+    collections::HashSet,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use moka::future::Cache;
+use serde::Serialize;
+use sqlx::{Postgres, QueryBuilder};
 use uuid::Uuid;
 
 use crate::{
     db::DbPool,
     error::AppError,
+    invalidation_bus::{Invalidation, InvalidationBus},
+    l2_cache::{L2Cache, L2Read},
+    metrics::{CacheName, Lookup, Metrics},
     model::Product,
+    listing::{ListingKey, SortValue},
+    page_index::PageIndex,
+    refresh::{replace_if_unchanged, RefreshSet, Timed},
 };
 
 // ---------------------------------------------------------------------------
@@ -53,10 +114,14 @@ pub type ProductCache = Arc<ProductCacheInner>;
 
 /// Tunables — change these or load from config/env in production.
 pub struct CacheConfig {
-    /// Hard cap on number of cached entries.
-    /// Each entry is ~200–400 bytes depending on Product size.
-    /// 10_000 entries × 400 B ≈ 4 MB maximum — well within typical limits.
-    pub max_capacity: u64,
+    /// Byte budget for `by_id`, as estimated by `product_weight`.
+    /// A product is ~150–400 bytes depending on its strings, so
+    /// 4 MiB holds roughly 10_000–25_000 products.
+    pub by_id_max_bytes: u64,
+
+    /// Byte budget for `by_category`. A full page of 20 products is ~5 KiB,
+    /// so 2 MiB holds roughly 400 pages.
+    pub by_category_max_bytes: u64,
 
     /// Entries expire this long after they were *written* into the cache.
     pub time_to_live: Duration,
@@ -64,14 +129,165 @@ pub struct CacheConfig {
     /// Entries expire this long after the *last read* — catches cold entries
     /// sooner than TTL alone.
     pub time_to_idle: Duration,
+
+    /// TTL of entries in the shared Redis tier, if one is attached.
+    /// L2 has no idle expiry, so keep this close to `time_to_live`.
+    pub l2_time_to_live: Duration,
+
+    /// Soft TTL for `by_id`. Older hits are served while one background
+    /// refresh reloads them; `time_to_live` remains the hard cap.
+    /// `None` disables refresh-ahead. Must be below `time_to_live` to matter.
+    pub by_id_soft_ttl: Option<Duration>,
+
+    /// Soft TTL for `by_category`, as `by_id_soft_ttl`.
+    pub by_category_soft_ttl: Option<Duration>,
+
+    /// Cap on remembered "no such product" IDs. Entries are a UUID each, so
+    /// this can be generous; it bounds what a scan of random IDs can pin.
+    pub negative_max_capacity: u64,
+
+    /// How long a "no such product" answer is trusted without asking the DB.
+    pub negative_time_to_live: Duration,
 }
 
 impl Default for CacheConfig {
     fn default() -> Self {
         Self {
-            max_capacity: 10_000,
+            by_id_max_bytes: 4 * 1024 * 1024,       // 4 MiB
+            by_category_max_bytes: 2 * 1024 * 1024, // 2 MiB
             time_to_live: Duration::from_secs(300),  // 5 min
             time_to_idle: Duration::from_secs(60),   // 1 min idle
+            l2_time_to_live: Duration::from_secs(300), // 5 min
+            by_id_soft_ttl: None,
+            by_category_soft_ttl: None,
+            negative_max_capacity: 10_000,
+            negative_time_to_live: Duration::from_secs(10),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Memory accounting
+// ---------------------------------------------------------------------------
+
+/// Current weighted size of each cache, for dashboards and alerts.
+///
+/// moka applies inserts and evictions in batches, so these may briefly run
+/// ahead of the budget between maintenance passes.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub by_id_bytes: u64,
+    pub by_id_entries: u64,
+    pub by_category_bytes: u64,
+    pub by_category_entries: u64,
+    pub not_found_entries: u64,
+}
+
+// ---------------------------------------------------------------------------
+// Entry inspection (admin API)
+// ---------------------------------------------------------------------------
+
+/// Age and remaining lifetime of one cached value.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryInfo {
+    pub age_secs: f64,
+    /// Time left before the hard TTL. An entry that sits idle can go sooner
+    /// (`time_to_idle`), and any entry can be evicted to stay in budget.
+    pub ttl_remaining_secs: Option<f64>,
+    /// Past its soft TTL: the next read will trigger a background refresh.
+    pub stale: bool,
+}
+
+impl EntryInfo {
+    fn of<V>(entry: &Timed<V>, ttl: Option<Duration>, soft_ttl: Option<Duration>) -> Self {
+        let age = entry.loaded_at.elapsed();
+        Self {
+            age_secs: age.as_secs_f64(),
+            ttl_remaining_secs: ttl.map(|ttl| ttl.saturating_sub(age).as_secs_f64()),
+            stale: entry.is_stale(soft_ttl),
+        }
+    }
+}
+
+/// What the cache holds for one product ID.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductEntry {
+    pub id: Uuid,
+    /// `None` when the product is not in L1.
+    pub cached: Option<EntryInfo>,
+    /// The ID is in the negative cache, i.e. reads answer 404 without the DB.
+    pub not_found_cached: bool,
+}
+
+/// One cached page of a category.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageEntry {
+    /// The query the page answers.
+    pub key: ListingKey,
+    pub products: usize,
+    #[serde(flatten)]
+    pub entry: EntryInfo,
+}
+
+/// Estimated heap footprint of one product: the struct plus its strings.
+pub fn product_weight(product: &Product) -> usize {
+    size_of::<Product>() + product.name.capacity() + product.category.capacity()
+}
+
+/// Estimated heap footprint of a page: the `Vec` and every product in it.
+/// Counts the `Vec`'s spare capacity, since it is allocated too.
+pub fn page_weight(page: &Arc<Vec<Product>>) -> usize {
+    let spare = page.capacity() - page.len();
+    size_of::<Vec<Product>>()
+        + spare * size_of::<Product>()
+        + page.iter().map(product_weight).sum::<usize>()
+}
+
+/// moka weights are `u32`; anything larger is certainly over budget anyway.
+fn saturate(bytes: usize) -> u32 {
+    u32::try_from(bytes).unwrap_or(u32::MAX)
+}
+
+// ---------------------------------------------------------------------------
+// Write descriptions
+// ---------------------------------------------------------------------------
+
+/// What a committed write did to a product — decides which category pages
+/// must go.
+pub enum ProductChange<'a> {
+    Created(&'a Product),
+    Updated { before: &'a Product, after: &'a Product },
+    Deleted(&'a Product),
+}
+
+impl ProductChange<'_> {
+    fn id(&self) -> Uuid {
+        match self {
+            ProductChange::Created(p) | ProductChange::Deleted(p) => p.id,
+            ProductChange::Updated { after, .. } => after.id,
+        }
+    }
+
+    /// Categories where the product may now belong on a page that does not
+    /// list it yet. Pages are keyset-paginated, sorted and filtered on name,
+    /// price and stock, so a change to any of them can move the product into
+    /// another page's range. Removing a product shifts nothing: the pages
+    /// that held it are dropped through the index.
+    fn reshuffled_categories(&self) -> Vec<String> {
+        match self {
+            ProductChange::Created(p) => vec![p.category.clone()],
+            ProductChange::Deleted(_) => Vec::new(),
+            ProductChange::Updated { before, after } if before.category != after.category => {
+                vec![before.category.clone(), after.category.clone()]
+            }
+            ProductChange::Updated { before, after }
+                if before.name != after.name
+                    || before.price_cents != after.price_cents
+                    || before.stock != after.stock =>
+            {
+                vec![after.category.clone()]
+            }
+            ProductChange::Updated { .. } => Vec::new(),
         }
     }
 }
@@ -82,37 +298,113 @@ impl Default for CacheConfig {
 
 pub struct ProductCacheInner {
     /// Single-product cache keyed by UUID.
-    by_id: Cache<Uuid, Arc<Product>>,
+    /// Values are timestamped so soft-TTL refresh can tell their age.
+    by_id: Cache<Uuid, Timed<Product>>,
 
-    /// List cache keyed by (category, page) — demonstrates composite keys.
+    /// List cache keyed by every listing parameter — demonstrates composite keys.
     /// Arc<Vec<..>> avoids cloning the whole vector on every cache hit.
-    by_category: Cache<(String, u32), Arc<Vec<Product>>>,
+    by_category: Cache<ListingKey, Timed<Vec<Product>>>,
+
+    /// IDs recently found not to exist (negative cache).
+    not_found: Cache<Uuid, ()>,
+
+    /// Which `by_category` pages hold which products.
+    page_index: Arc<PageIndex>,
+
+    /// Soft TTLs and in-flight background refreshes, per cache.
+    by_id_soft_ttl: Option<Duration>,
+    by_category_soft_ttl: Option<Duration>,
+    refreshing_ids: Arc<RefreshSet<Uuid>>,
+    refreshing_pages: Arc<RefreshSet<ListingKey>>,
+
+    /// Optional shared second tier, consulted on an L1 miss before the DB.
+    l2: Option<L2Cache>,
+
+    /// Optional pub/sub bus that tells other replicas what to invalidate.
+    bus: Option<InvalidationBus>,
+
+    /// Hit/miss/eviction counters and DB latency, exported on `/metrics`.
+    metrics: Arc<Metrics>,
 
     /// A reference to the DB pool so the cache can self-populate (cache-aside).
     db: DbPool,
 }
 
 impl ProductCacheInner {
-    pub fn new(db: DbPool, cfg: CacheConfig) -> Self {
+    pub fn new(db: DbPool, cfg: CacheConfig, metrics: Arc<Metrics>) -> Self {
+        // With a weigher, max_capacity is a total weight — here, bytes.
+        let by_id_metrics = metrics.clone();
         let by_id = Cache::builder()
-            .max_capacity(cfg.max_capacity)
+            .max_capacity(cfg.by_id_max_bytes)
+            .weigher(|_id: &Uuid, entry: &Timed<Product>| {
+                saturate(size_of::<Uuid>() + size_of::<Timed<Product>>() + product_weight(&entry.value))
+            })
             .time_to_live(cfg.time_to_live)
             .time_to_idle(cfg.time_to_idle)
-            // Optional: byte-level weigher for tighter OOM control.
-            // The weight of each entry is passed to the eviction policy.
-            // .weigher(|_k, v: &Arc<Product>| {
-            //     // Rough size estimate in bytes; tune per your actual struct.
-            //     (std::mem::size_of::<Product>() + v.name.len() + 16) as u32
-            // })
+            .eviction_listener(move |_id, _entry, cause| by_id_metrics.record_eviction(CacheName::ById, cause))
             .build();
 
+        let page_index = Arc::new(PageIndex::default());
+        let listener_index = page_index.clone();
+        let by_category_metrics = metrics.clone();
         let by_category = Cache::builder()
-            .max_capacity(256) // far fewer list results
+            .max_capacity(cfg.by_category_max_bytes)
+            .weigher(|key: &ListingKey, entry: &Timed<Vec<Product>>| {
+                saturate(key.weight() + size_of::<Timed<Vec<Product>>>() + page_weight(&entry.value))
+            })
             .time_to_live(cfg.time_to_live)
             .time_to_idle(cfg.time_to_idle)
+            // Keep the index no larger than the cache, whatever the removal cause.
+            .eviction_listener(move |key: Arc<ListingKey>, page: Timed<Vec<Product>>, cause| {
+                listener_index.forget(&key, &page.value);
+                by_category_metrics.record_eviction(CacheName::ByCategory, cause);
+            })
             .build();
 
-        Self { by_id, by_category, db }
+        let not_found_metrics = metrics.clone();
+        let not_found = Cache::builder()
+            .max_capacity(cfg.negative_max_capacity)
+            .time_to_live(cfg.negative_time_to_live)
+            .eviction_listener(move |_id, _, cause| not_found_metrics.record_eviction(CacheName::NotFound, cause))
+            .build();
+
+        Self {
+            by_id,
+            by_category,
+            not_found,
+            page_index,
+            by_id_soft_ttl: cfg.by_id_soft_ttl,
+            by_category_soft_ttl: cfg.by_category_soft_ttl,
+            refreshing_ids: Arc::default(),
+            refreshing_pages: Arc::default(),
+            l2: None,
+            bus: None,
+            metrics,
+            db,
+        }
+    }
+
+    /// Weighted size of every cache right now.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            by_id_bytes: self.by_id.weighted_size(),
+            by_id_entries: self.by_id.entry_count(),
+            by_category_bytes: self.by_category.weighted_size(),
+            by_category_entries: self.by_category.entry_count(),
+            not_found_entries: self.not_found.entry_count(),
+        }
+    }
+
+    /// Put a shared Redis tier behind the in-process caches.
+    pub fn with_l2(mut self, l2: L2Cache) -> Self {
+        self.l2 = Some(l2);
+        self
+    }
+
+    /// Publish every invalidation to the other replicas.
+    pub fn with_invalidation_bus(mut self, bus: InvalidationBus) -> Self {
+        self.bus = Some(bus);
+        self
     }
 
     // -----------------------------------------------------------------------
@@ -126,25 +418,127 @@ impl ProductCacheInner {
     ///   share a single DB future.  The initialiser runs exactly once; all
     ///   other callers await its result.  No second query can overwrite the
     ///   first result out-of-order.
-    pub async fn get_product(&self, id: Uuid) -> Result<Arc<Product>, AppError> {
-        self.by_id
-            .try_get_with(id, self.fetch_product_from_db(id))
-            .await
-            .map_err(|e| AppError::Cache(e.to_string()))
+    pub async fn get_product(self: &Arc<Self>, id: Uuid) -> Result<Arc<Product>, AppError> {
+        if self.not_found.contains_key(&id) {
+            self.metrics.record_lookup(CacheName::NotFound, Lookup::Hit);
+            return Err(AppError::NotFound(id));
+        }
+
+        let cached = self.by_id.contains_key(&id);
+        let loaded = AtomicBool::new(false);
+        let entry = self
+            .by_id
+            .try_get_with(id, async {
+                loaded.store(true, Ordering::Relaxed);
+                match self.load_product(id).await {
+                    Ok(product) => Ok(Timed::new(product)),
+                    Err(AppError::NotFound(_)) => {
+                        // Inside the initialiser, so concurrent misses record it once.
+                        self.not_found.insert(id, ()).await;
+                        Err(AppError::NotFound(id))
+                    }
+                    Err(e) => Err(e),
+                }
+            })
+            .await;
+        self.metrics
+            .record_lookup(CacheName::ById, Lookup::classify(cached, loaded.into_inner()));
+        let entry = entry.map_err(cache_error)?;
+
+        if entry.is_stale(self.by_id_soft_ttl) {
+            self.spawn_product_refresh(id, entry.value.clone());
+        }
+        Ok(entry.value)
     }
 
-    /// Fetch a page of products in a category.
+    /// Fetch one page of a category listing.
     pub async fn get_products_by_category(
-        &self,
-        category: &str,
-        page: u32,
+        self: &Arc<Self>,
+        key: ListingKey,
     ) -> Result<Arc<Vec<Product>>, AppError> {
-        let key = (category.to_owned(), page);
+        let cached = self.by_category.contains_key(&key);
+        let loaded = AtomicBool::new(false);
+        let entry = self
+            .by_category
+            .try_get_with(key.clone(), async {
+                loaded.store(true, Ordering::Relaxed);
+                let products = self.load_category_page(&key).await?;
+                self.page_index.record(&key, &products);
+                Ok::<_, AppError>(Timed::new(products))
+            })
+            .await;
+        self.metrics
+            .record_lookup(CacheName::ByCategory, Lookup::classify(cached, loaded.into_inner()));
+        let entry = entry.map_err(cache_error)?;
+
+        if entry.is_stale(self.by_category_soft_ttl) {
+            self.spawn_page_refresh(key, entry.value.clone());
+        }
+        Ok(entry.value)
+    }
+
+    // -----------------------------------------------------------------------
+    // Background refresh (soft TTL)
+    // -----------------------------------------------------------------------
+
+    /// Reload `id` from the DB off the request path, unless a refresh for it
+    /// is already running. Goes straight to the DB: L2 may be just as old.
+    ///
+    /// The result only goes to L1, and only if the stale entry is still
+    /// there. It never goes to L2: a write may commit and invalidate while the
+    /// DB read is in flight, and L2 has no entry to compare against, so the
+    /// pre-write value would come back for every replica.
+    fn spawn_product_refresh(self: &Arc<Self>, id: Uuid, stale: Arc<Product>) {
+        let Some(guard) = self.refreshing_ids.try_start(&id) else {
+            return;
+        };
+        let this = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let product = match this.fetch_product_from_db(id).await {
+                Ok(product) => product,
+                Err(e) => {
+                    // Keep serving the stale value until the hard TTL.
+                    tracing::warn!(%id, error = %e, "background refresh failed");
+                    return;
+                }
+            };
+            this.by_id
+                .entry(id)
+                .and_compute_with(|current| replace_if_unchanged(current, &stale, Timed::new(product)))
+                .await;
+        });
+    }
 
-        self.by_category
-            .try_get_with(key, self.fetch_category_page_from_db(category, page))
-            .await
-            .map_err(|e| AppError::Cache(e.to_string()))
+    /// As `spawn_product_refresh`, for one category page.
+    fn spawn_page_refresh(self: &Arc<Self>, key: ListingKey, stale: Arc<Vec<Product>>) {
+        let Some(guard) = self.refreshing_pages.try_start(&key) else {
+            return;
+        };
+        let this = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let products = match this.fetch_category_page_from_db(&key).await {
+                Ok(products) => products,
+                Err(e) => {
+                    tracing::warn!(?key, error = %e, "background refresh failed");
+                    return;
+                }
+            };
+            // Record before the swap so the page is never cached unindexed;
+            // the replaced value's eviction only forgets its own entries.
+            this.page_index.record(&key, &products);
+            let fresh = Timed::new(products.clone());
+            let result = this
+                .by_category
+                .entry(key.clone())
+                .and_compute_with(|current| replace_if_unchanged(current, &stale, fresh))
+                .await;
+            if !matches!(result, moka::ops::compute::CompResult::ReplacedWith(_)) {
+                // Not installed: drop what we just recorded.
+                this.page_index.forget(&key, &products);
+            }
+        });
     }
 
     // -----------------------------------------------------------------------
@@ -156,62 +550,526 @@ impl ProductCacheInner {
     /// REORDER SAFETY: `.invalidate()` is asynchronous — it waits until
     /// any pending background write for this key completes before removing
     /// the entry. Subsequent reads will hit the DB and re-populate.
-    pub async fn invalidate_product(&self, id: Uuid) {
-        self.by_id.invalidate(&id).await;
-        // Also invalidate all category pages — they may contain the product.
-        self.by_category.invalidate_all();
+    pub async fn invalidate_product(&self, change: ProductChange<'_>) {
+        let id = change.id();
+        let categories = change.reshuffled_categories();
+
+        if let Some(l2) = &self.l2 {
+            l2.invalidate_products(&[id], &categories).await;
+        }
+        self.invalidate_product_local(id, Some(&categories)).await;
+        if let Some(bus) = &self.bus {
+            bus.publish(Invalidation::Product { id, categories: Some(categories) }).await;
+        }
+    }
+
+    /// As `invalidate_product`, for a batch of committed writes (bulk
+    /// import): one L2 round trip per kind of key and one bus message,
+    /// instead of one of each per product.
+    pub async fn invalidate_products(&self, changes: &[ProductChange<'_>]) {
+        if changes.is_empty() {
+            return;
+        }
+        let ids: Vec<Uuid> = changes.iter().map(ProductChange::id).collect();
+        let mut categories: Vec<String> = changes.iter().flat_map(ProductChange::reshuffled_categories).collect();
+        categories.sort_unstable();
+        categories.dedup();
+
+        if let Some(l2) = &self.l2 {
+            l2.invalidate_products(&ids, &categories).await;
+        }
+        self.invalidate_products_local(&ids, &categories).await;
+        if let Some(bus) = &self.bus {
+            bus.publish(Invalidation::Products { ids, categories }).await;
+        }
     }
 
-    /// Drain the entire cache (e.g., after a bulk import).
+    /// Drain the entire cache.
     pub async fn invalidate_all(&self) {
+        if let Some(l2) = &self.l2 {
+            l2.invalidate_all().await;
+        }
+        self.flush_local().await;
+        if let Some(bus) = &self.bus {
+            bus.publish(Invalidation::All).await;
+        }
+    }
+
+    /// Apply an invalidation published by another replica.
+    pub async fn apply_remote(&self, invalidation: &Invalidation) {
+        match invalidation {
+            Invalidation::Product { id, categories } => {
+                self.invalidate_product_local(*id, categories.as_deref()).await
+            }
+            Invalidation::Products { ids, categories } => {
+                self.invalidate_products_local(ids, categories).await
+            }
+            Invalidation::Category { category } => self.invalidate_category_local(category).await,
+            Invalidation::All => self.flush_local().await,
+        }
+    }
+
+    /// Apply an invalidation for a write seen through Postgres NOTIFY, which
+    /// may not have come through this API. Every replica receives it, so it
+    /// clears L2 and this replica's L1 but is not published on the bus.
+    pub async fn apply_external(&self, invalidation: &Invalidation) {
+        if let Some(l2) = &self.l2 {
+            match invalidation {
+                Invalidation::Product { id, categories: Some(categories) } => {
+                    l2.invalidate_products(&[*id], categories).await
+                }
+                Invalidation::Product { id, categories: None } => {
+                    l2.invalidate_products(&[*id], &[]).await;
+                    l2.invalidate_category_pages().await;
+                }
+                Invalidation::Products { ids, categories } => l2.invalidate_products(ids, categories).await,
+                Invalidation::Category { category } => {
+                    l2.invalidate_products(&[], std::slice::from_ref(category)).await
+                }
+                Invalidation::All => l2.invalidate_all().await,
+            }
+        }
+        self.apply_remote(invalidation).await;
+    }
+
+    /// Drop everything from L1 only — used when the bus may have missed
+    /// messages, so any local entry could be stale.
+    pub async fn flush_local(&self) {
         self.by_id.invalidate_all();
         self.by_category.invalidate_all();
+        self.not_found.invalidate_all();
         // run_pending_tasks() flushes eviction events synchronously.
         self.by_id.run_pending_tasks().await;
         self.by_category.run_pending_tasks().await;
     }
 
+    /// Drop product `id`, the cached pages holding it, and every page of
+    /// `reshuffled` categories (`None`: of all categories).
+    async fn invalidate_product_local(&self, id: Uuid, reshuffled: Option<&[String]>) {
+        // A create must be visible at once, not after the negative TTL.
+        self.not_found.invalidate(&id).await;
+        self.by_id.invalidate(&id).await;
+
+        let Some(categories) = reshuffled else {
+            self.by_category.invalidate_all();
+            return;
+        };
+
+        let mut pages = self.page_index.pages_containing(id);
+        for category in categories {
+            pages.extend(self.page_index.pages_in_category(category));
+        }
+        for key in pages {
+            self.by_category.invalidate(&key).await;
+        }
+    }
+
+    /// Drop each of `ids`, the cached pages holding them, and every page of
+    /// `categories` — each page once, however many products it holds.
+    async fn invalidate_products_local(&self, ids: &[Uuid], categories: &[String]) {
+        let mut pages = HashSet::new();
+        for id in ids {
+            self.not_found.invalidate(id).await;
+            self.by_id.invalidate(id).await;
+            pages.extend(self.page_index.pages_containing(*id));
+        }
+        for category in categories {
+            pages.extend(self.page_index.pages_in_category(category));
+        }
+        for key in pages {
+            self.by_category.invalidate(&key).await;
+        }
+    }
+
+    /// Drop every cached page of `category` from L1 only.
+    async fn invalidate_category_local(&self, category: &str) {
+        for key in self.page_index.pages_in_category(category) {
+            self.by_category.invalidate(&key).await;
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Admin operations
+    // -----------------------------------------------------------------------
+
+    /// Whether `id` is cached, and for how much longer.
+    /// Reading the entry counts as an access, so it resets its idle timer.
+    pub async fn inspect_product(&self, id: Uuid) -> ProductEntry {
+        let ttl = self.by_id.policy().time_to_live();
+        ProductEntry {
+            id,
+            cached: self
+                .by_id
+                .get(&id)
+                .await
+                .map(|entry| EntryInfo::of(&entry, ttl, self.by_id_soft_ttl)),
+            not_found_cached: self.not_found.contains_key(&id),
+        }
+    }
+
+    /// Every cached page of `category`, of any query.
+    pub async fn inspect_category(&self, category: &str) -> Vec<PageEntry> {
+        let ttl = self.by_category.policy().time_to_live();
+        let mut pages = Vec::new();
+        for key in self.page_index.pages_in_category(category) {
+            if let Some(entry) = self.by_category.get(&key).await {
+                pages.push(PageEntry {
+                    products: entry.value.len(),
+                    entry: EntryInfo::of(&entry, ttl, self.by_category_soft_ttl),
+                    key,
+                });
+            }
+        }
+        pages
+    }
+
+    /// Drop product `id` (and the cached pages listing it) everywhere,
+    /// without a write having happened.
+    pub async fn evict_product(&self, id: Uuid) {
+        if let Some(l2) = &self.l2 {
+            l2.invalidate_products(&[id], &[]).await;
+        }
+        self.invalidate_product_local(id, Some(&[])).await;
+        if let Some(bus) = &self.bus {
+            bus.publish(Invalidation::Product { id, categories: Some(Vec::new()) }).await;
+        }
+    }
+
+    /// Drop every cached page of `category` everywhere.
+    pub async fn evict_category(&self, category: &str) {
+        if let Some(l2) = &self.l2 {
+            l2.invalidate_products(&[], &[category.to_owned()]).await;
+        }
+        self.invalidate_category_local(category).await;
+        if let Some(bus) = &self.bus {
+            bus.publish(Invalidation::Category { category: category.to_owned() }).await;
+        }
+    }
+
+    /// The `limit` categories with the most products.
+    pub async fn top_categories(&self, limit: u32) -> Result<Vec<String>, AppError> {
+        let categories = sqlx::query_scalar::<_, String>(
+            "SELECT category
+               FROM products
+              GROUP BY category
+              ORDER BY count(*) DESC, category
+              LIMIT $1",
+        )
+        .bind(i64::from(limit))
+        .fetch_all(&self.db)
+        .await?;
+        Ok(categories)
+    }
+
+    /// Load the first `pages` pages of each category's default listing in
+    /// the background, through the normal read path (so loads coalesce with
+    /// live traffic). A category stops at its last page.
+    pub fn spawn_warm(self: &Arc<Self>, categories: Vec<String>, pages: u32) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            for category in &categories {
+                let mut key = Some(ListingKey::first_page(category));
+                for page in 0..pages {
+                    let Some(current) = key.take() else { break };
+                    match this.get_products_by_category(current.clone()).await {
+                        Ok(products) => key = current.next_page(&products),
+                        Err(e) => {
+                            tracing::warn!(category, page, error = %e, "cache warm failed");
+                            break;
+                        }
+                    }
+                }
+            }
+            tracing::info!(categories = categories.len(), "cache warm finished");
+        });
+    }
+
+    // -----------------------------------------------------------------------
+    // L1 initialisers: L2, then DB
+    // -----------------------------------------------------------------------
+
+    async fn load_product(&self, id: Uuid) -> Result<Arc<Product>, AppError> {
+        let Some(l2) = &self.l2 else {
+            return self.fetch_product_from_db(id).await;
+        };
+
+        let token = match l2.read::<Product>(&l2.product_key(id)).await {
+            L2Read::Hit(product) => return Ok(Arc::new(product)),
+            L2Read::Miss(token) => token,
+        };
+
+        let product = self.fetch_product_from_db(id).await?;
+        // Conditional: skipped if a write invalidated the product meanwhile.
+        if let Some(token) = token {
+            l2.fill_product(&product, token).await;
+        }
+        Ok(product)
+    }
+
+    async fn load_category_page(&self, key: &ListingKey) -> Result<Arc<Vec<Product>>, AppError> {
+        let Some(l2) = &self.l2 else {
+            return self.fetch_category_page_from_db(key).await;
+        };
+
+        let token = match l2.read::<Vec<Product>>(&l2.category_page_key(key)).await {
+            L2Read::Hit(products) => return Ok(Arc::new(products)),
+            L2Read::Miss(token) => token,
+        };
+
+        let products = self.fetch_category_page_from_db(key).await?;
+        if let Some(token) = token {
+            l2.fill_category_page(key, &products, token).await;
+        }
+        Ok(products)
+    }
+
     // -----------------------------------------------------------------------
     // Internal DB fetchers
     // -----------------------------------------------------------------------
 
     async fn fetch_product_from_db(&self, id: Uuid) -> Result<Arc<Product>, AppError> {
+        let started = Instant::now();
         let product = sqlx::query_as::<_, Product>(
-            "SELECT id, name, category, price_cents, stock, updated_at
+            "SELECT id, name, category, price_cents, stock, updated_at, version
                FROM products
               WHERE id = $1",
         )
         .bind(id)
         .fetch_optional(&self.db)
-        .await?
-        .ok_or(AppError::NotFound(id))?;
+        .await;
+        self.metrics.record_db_fetch("product", started);
+        let product = product?.ok_or(AppError::NotFound(id))?;
 
         tracing::debug!(%id, "cache miss — fetched product from DB");
         Ok(Arc::new(product))
     }
 
-    async fn fetch_category_page_from_db(
-        &self,
-        category: &str,
-        page: u32,
-    ) -> Result<Arc<Vec<Product>>, AppError> {
-        const PAGE_SIZE: i64 = 20;
-        let offset = i64::from(page) * PAGE_SIZE;
-
-        let products = sqlx::query_as::<_, Product>(
-            "SELECT id, name, category, price_cents, stock, updated_at
+    /// One keyset page: rows strictly after the cursor in sort order, so
+    /// the query costs the same however deep the page is.
+    async fn fetch_category_page_from_db(&self, key: &ListingKey) -> Result<Arc<Vec<Product>>, AppError> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT id, name, category, price_cents, stock, updated_at, version
                FROM products
-              WHERE category = $1
-              ORDER BY name
-              LIMIT $2 OFFSET $3",
-        )
-        .bind(category)
-        .bind(PAGE_SIZE)
-        .bind(offset)
-        .fetch_all(&self.db)
-        .await?;
+              WHERE category = ",
+        );
+        query.push_bind(key.category.clone());
+        if let Some(min) = key.min_price_cents {
+            query.push(" AND price_cents >= ").push_bind(min);
+        }
+        if let Some(max) = key.max_price_cents {
+            query.push(" AND price_cents <= ").push_bind(max);
+        }
+        if let Some(min) = key.min_stock {
+            query.push(" AND stock >= ").push_bind(min);
+        }
+        if let Some(max) = key.max_stock {
+            query.push(" AND stock <= ").push_bind(max);
+        }
 
-        tracing::debug!(category, page, count = products.len(), "cache miss — fetched page from DB");
+        // Column names come from `Sort`, never from the request.
+        let column = key.sort.column();
+        let (after, direction) = if key.sort.descending() { ("<", "DESC") } else { (">", "ASC") };
+        if let Some(cursor) = &key.after {
+            query.push(format_args!(" AND ({column}, id) {after} ("));
+            match &cursor.value {
+                SortValue::Name(name) => query.push_bind(name.clone()),
+                SortValue::Price(price) => query.push_bind(*price),
+            };
+            query.push(", ").push_bind(cursor.id).push(")");
+        }
+        query
+            .push(format_args!(" ORDER BY {column} {direction}, id {direction} LIMIT "))
+            .push_bind(i64::from(key.limit));
+
+        let started = Instant::now();
+        let products = query.build_query_as::<Product>().fetch_all(&self.db).await;
+        self.metrics.record_db_fetch("category_page", started);
+        let products = products?;
+
+        tracing::debug!(?key, count = products.len(), "cache miss — fetched page from DB");
         Ok(Arc::new(products))
     }
 }
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// moka hands initialiser errors back as `Arc<AppError>`. Keep `NotFound` a
+/// 404 instead of flattening it into an opaque cache error (500).
+fn cache_error(e: Arc<AppError>) -> AppError {
+    match *e {
+        AppError::NotFound(id) => AppError::NotFound(id),
+        _ => AppError::Cache(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::listing::Sort;
+
+    fn product(category: &str, name_len: usize) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "x".repeat(name_len),
+            category: category.to_owned(),
+            price_cents: 100,
+            stock: 1,
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    /// A cache built exactly as in production, over a pool that never connects.
+    fn cache(by_id_max_bytes: u64, by_category_max_bytes: u64) -> ProductCacheInner {
+        let db = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .expect("lazy pool");
+        let cfg = CacheConfig {
+            by_id_max_bytes,
+            by_category_max_bytes,
+            ..CacheConfig::default()
+        };
+        ProductCacheInner::new(db, cfg, Arc::new(Metrics::new().expect("metrics")))
+    }
+
+    /// Store `page` under `key` the way `get_products_by_category` does.
+    async fn cache_page(cache: &ProductCacheInner, key: &ListingKey, page: Vec<Product>) {
+        let page = Arc::new(page);
+        cache.page_index.record(key, &page);
+        cache.by_category.insert(key.clone(), Timed::new(page)).await;
+    }
+
+    #[tokio::test]
+    async fn test_oom_safety_byte_budget() {
+        const BUDGET: u64 = 64 * 1024;
+        let cache = cache(BUDGET, BUDGET);
+
+        // Mixed sizes: many ordinary products and pages, every tenth oversized.
+        for i in 0..2_000usize {
+            let len = if i % 10 == 0 { 8 * 1024 } else { 20 };
+            let p = product("books", len);
+            cache.by_id.insert(p.id, Timed::new(Arc::new(p))).await;
+        }
+        for i in 0..500usize {
+            let len = if i % 10 == 0 { 2 * 1024 } else { 20 };
+            let page: Vec<Product> = (0..20).map(|_| product("books", len)).collect();
+            let key = ListingKey::first_page(&format!("category-{i}"));
+            cache.by_category.insert(key, Timed::new(Arc::new(page))).await;
+        }
+        cache.by_id.run_pending_tasks().await;
+        cache.by_category.run_pending_tasks().await;
+
+        let stats = cache.stats();
+        assert!(stats.by_id_bytes <= BUDGET, "by_id holds {} bytes, budget {BUDGET}", stats.by_id_bytes);
+        assert!(
+            stats.by_category_bytes <= BUDGET,
+            "by_category holds {} bytes, budget {BUDGET}",
+            stats.by_category_bytes
+        );
+        // Counting entries would have let all of them in; the byte cap does not.
+        assert!(stats.by_id_entries < 2_000);
+        assert!(stats.by_category_entries < 500);
+    }
+
+    #[tokio::test]
+    async fn test_oom_safety_oversized_entry_rejected() {
+        const BUDGET: u64 = 4 * 1024;
+        let cache = cache(BUDGET, BUDGET);
+
+        let small = product("books", 10);
+        let huge = product("books", BUDGET as usize * 2);
+        let (small_id, huge_id) = (small.id, huge.id);
+        cache.by_id.insert(small_id, Timed::new(Arc::new(small))).await;
+        cache.by_id.insert(huge_id, Timed::new(Arc::new(huge))).await;
+
+        let small_page = ListingKey::first_page("small");
+        let huge_page = ListingKey::first_page("huge");
+        cache
+            .by_category
+            .insert(small_page.clone(), Timed::new(Arc::new(vec![product("small", 10)])))
+            .await;
+        cache
+            .by_category
+            .insert(huge_page.clone(), Timed::new(Arc::new(vec![product("huge", BUDGET as usize * 2)])))
+            .await;
+
+        cache.by_id.run_pending_tasks().await;
+        cache.by_category.run_pending_tasks().await;
+
+        assert!(cache.by_id.get(&huge_id).await.is_none(), "oversized product must not be kept");
+        assert!(cache.by_id.get(&small_id).await.is_some());
+        assert!(cache.by_category.get(&huge_page).await.is_none(), "oversized page must not be kept");
+        assert!(cache.by_category.get(&small_page).await.is_some());
+
+        let stats = cache.stats();
+        assert!(stats.by_id_bytes <= BUDGET);
+        assert!(stats.by_category_bytes <= BUDGET);
+    }
+
+    #[tokio::test]
+    async fn test_delete_drops_only_pages_holding_the_product() {
+        let cache = cache(1 << 20, 1 << 20);
+        let (gone, kept, other) = (product("tools", 10), product("tools", 10), product("toys", 10));
+        let by_name = ListingKey::first_page("tools");
+        let by_price = ListingKey { sort: Sort::PriceAsc, ..ListingKey::first_page("tools") };
+        let toys = ListingKey::first_page("toys");
+        cache_page(&cache, &by_name, vec![gone.clone(), kept.clone()]).await;
+        cache_page(&cache, &by_price, vec![kept.clone()]).await;
+        cache_page(&cache, &toys, vec![other]).await;
+
+        cache.invalidate_product(ProductChange::Deleted(&gone)).await;
+        cache.by_category.run_pending_tasks().await;
+
+        assert!(cache.by_category.get(&by_name).await.is_none());
+        assert!(cache.by_category.get(&by_price).await.is_some(), "page without the product must survive");
+        assert!(cache.by_category.get(&toys).await.is_some(), "other categories must survive");
+        assert!(cache.page_index.pages_containing(gone.id).is_empty(), "index must follow evictions");
+    }
+
+    #[tokio::test]
+    async fn test_category_move_drops_old_and_new_category_pages() {
+        let cache = cache(1 << 20, 1 << 20);
+        let before = product("tools", 10);
+        let after = Product { category: "toys".into(), ..before.clone() };
+        let tools = ListingKey::first_page("tools");
+        let tools_by_price = ListingKey { sort: Sort::PriceAsc, ..ListingKey::first_page("tools") };
+        let toys = ListingKey::first_page("toys");
+        let garden = ListingKey::first_page("garden");
+        cache_page(&cache, &tools, vec![before.clone()]).await;
+        cache_page(&cache, &tools_by_price, vec![product("tools", 10)]).await;
+        // The product now sorts onto this page, which does not list it yet.
+        cache_page(&cache, &toys, vec![product("toys", 10)]).await;
+        cache_page(&cache, &garden, vec![product("garden", 10)]).await;
+
+        cache
+            .invalidate_product(ProductChange::Updated { before: &before, after: &after })
+            .await;
+
+        assert!(cache.by_category.get(&tools).await.is_none());
+        assert!(cache.by_category.get(&tools_by_price).await.is_none());
+        assert!(cache.by_category.get(&toys).await.is_none());
+        assert!(cache.by_category.get(&garden).await.is_some(), "unrelated category must survive");
+    }
+
+    #[tokio::test]
+    async fn test_negative_cache_answers_without_the_db() {
+        let cache = Arc::new(cache(1 << 20, 1 << 20));
+        let missing = product("tools", 10);
+        cache.not_found.insert(missing.id, ()).await;
+
+        // The pool never connects, so anything but NotFound means the DB was tried.
+        for _ in 0..3 {
+            let result = cache.get_product(missing.id).await;
+            assert!(matches!(result, Err(AppError::NotFound(id)) if id == missing.id));
+        }
+        assert!(cache.by_id.get(&missing.id).await.is_none());
+
+        cache.invalidate_product(ProductChange::Created(&missing)).await;
+        assert!(!cache.not_found.contains_key(&missing.id), "a create must clear the negative entry");
+    }
+}
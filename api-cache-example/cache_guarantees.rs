@@ -187,3 +187,72 @@ async fn test_ttl_expiry() {
         "Expired entry must trigger a fresh fetch"
     );
 }
+
+// ---------------------------------------------------------------------------
+// 6. TWO TIERS — L1 → L2 → DB, still one fetch per key
+// ---------------------------------------------------------------------------
+
+/// Stand-in for the shared Redis tier: serialized values plus a read counter.
+#[derive(Clone, Default)]
+struct MockL2 {
+    entries: Arc<std::sync::Mutex<std::collections::HashMap<u32, String>>>,
+    reads: FetchCount,
+}
+
+impl MockL2 {
+    async fn load(&self, key: u32, db_fetches: FetchCount) -> Result<Arc<String>, String> {
+        // Simulate network latency so concurrent callers overlap.
+        sleep(Duration::from_millis(10)).await;
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        if let Some(raw) = self.entries.lock().unwrap().get(&key) {
+            return Ok(Arc::new(serde_json::from_str(raw).unwrap()));
+        }
+
+        sleep(Duration::from_millis(20)).await;
+        db_fetches.fetch_add(1, Ordering::SeqCst);
+        let value = format!("product-{key}");
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key, serde_json::to_string(&value).unwrap());
+        Ok(Arc::new(value))
+    }
+}
+
+#[tokio::test]
+async fn test_two_tier_coalescing_and_warm_replica() {
+    let l2 = MockL2::default();
+    let db_fetches = counter();
+
+    // Two replicas, each with its own L1, sharing one L2.
+    let replica_a: Cache<u32, Arc<String>> = Cache::builder().max_capacity(100).build();
+    let replica_b: Cache<u32, Arc<String>> = Cache::builder().max_capacity(100).build();
+
+    let herd = |cache: Cache<u32, Arc<String>>| {
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let (c, l2, db) = (cache.clone(), l2.clone(), db_fetches.clone());
+                tokio::spawn(async move { c.try_get_with(7, l2.load(7, db)).await.unwrap() })
+            })
+            .collect();
+        async move {
+            for t in tasks {
+                assert_eq!(*t.await.unwrap(), "product-7");
+            }
+        }
+    };
+
+    // Cold start: the L2 lookup and DB fetch both happen once for 50 callers.
+    herd(replica_a.clone()).await;
+    assert_eq!(l2.reads.load(Ordering::SeqCst), 1, "L2 read must be coalesced per key");
+    assert_eq!(db_fetches.load(Ordering::SeqCst), 1);
+
+    // A fresh replica warms from L2 — one L2 read, no DB traffic at all.
+    herd(replica_b).await;
+    assert_eq!(l2.reads.load(Ordering::SeqCst), 2);
+    assert_eq!(db_fetches.load(Ordering::SeqCst), 1, "Warm L2 must shield the DB");
+
+    // L1 hits touch neither tier.
+    herd(replica_a).await;
+    assert_eq!(l2.reads.load(Ordering::SeqCst), 2);
+}
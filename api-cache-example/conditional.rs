@@ -0,0 +1,279 @@
+/// conditional.rs — ETag / Last-Modified validators and conditional requests
+///
+/// Design guarantees:
+///
+/// 1. VALIDATORS COME FROM THE CACHED VALUE
+///    - A product's ETag is its row `version`; a page's ETag hashes the
+///      `(id, version)` of every product on it. Both are computed from the
+///      value the response is built from, so a 304 never vouches for a body
+///      we would not have sent.
+///
+/// 2. STABLE ACROSS REPLICAS
+///    - The page hash is FNV-1a over fixed-width fields, not `std`'s
+///      `DefaultHasher`, so every instance (and every build) produces the same
+///      ETag for the same page.
+///
+/// 3. PRECONDITIONS ARE CHECKED BY THE DATABASE
+///    - `If-Match` tags are decoded back into versions and checked in the
+///      `UPDATE`'s `WHERE` clause, never against a cached row that may
+///      be stale.
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+
+use crate::model::Product;
+
+// ---------------------------------------------------------------------------
+// Validators
+// ---------------------------------------------------------------------------
+
+/// Strong ETag of one product.
+pub fn product_etag(product: &Product) -> String {
+    format!("\"{}\"", product.version)
+}
+
+/// Strong ETag of a list page: changes when any product on it is added,
+/// removed, reordered or updated.
+pub fn page_etag(products: &[Product]) -> String {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET;
+    for product in products {
+        let version = product.version.to_be_bytes();
+        for byte in product.id.as_bytes().iter().chain(&version) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    format!("\"p{hash:016x}\"")
+}
+
+/// A timestamp as an HTTP-date (whole seconds, GMT).
+pub fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// `ETag` (and `Last-Modified`, if given) response headers.
+pub fn validator_headers(etag: &str, last_modified: Option<DateTime<Utc>>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(Ok(value)) = last_modified.map(|at| HeaderValue::from_str(&http_date(at))) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    headers
+}
+
+// ---------------------------------------------------------------------------
+// Conditional GET
+// ---------------------------------------------------------------------------
+
+/// True if the client's copy is current and a 304 should be sent.
+///
+/// Per RFC 9110 §13.2.2, `If-None-Match` takes precedence: when it is present
+/// `If-Modified-Since` is ignored. `last_modified` is `None` for resources
+/// that have no trustworthy modification time (list pages: a deletion does
+/// not move any remaining product's `updated_at`).
+pub fn is_not_modified(request: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+        let Ok(tags) = if_none_match.to_str() else {
+            return false;
+        };
+        // Weak comparison: `W/"x"` matches `"x"`.
+        return tags.trim() == "*" || split_tags(tags).any(|tag| strip_weak(tag) == etag);
+    }
+
+    let (Some(last_modified), Some(since)) = (last_modified, request.get(header::IF_MODIFIED_SINCE)) else {
+        return false;
+    };
+    let Some(since) = since.to_str().ok().and_then(|s| DateTime::parse_from_rfc2822(s).ok()) else {
+        return false;
+    };
+    // HTTP-dates have whole-second resolution.
+    last_modified.timestamp() <= since.timestamp()
+}
+
+// ---------------------------------------------------------------------------
+// If-Match
+// ---------------------------------------------------------------------------
+
+/// A parsed `If-Match` header.
+pub enum IfMatch {
+    /// `If-Match: *` — any current representation.
+    Any,
+    /// The product versions the client will accept. Tags we did not issue
+    /// decode to nothing, and so can never match.
+    Versions(Vec<i64>),
+}
+
+/// `None` if the request has no `If-Match` header.
+pub fn if_match(request: &HeaderMap) -> Option<IfMatch> {
+    let tags = request.get(header::IF_MATCH)?.to_str().unwrap_or("");
+    if tags.trim() == "*" {
+        return Some(IfMatch::Any);
+    }
+    // If-Match uses strong comparison, so weak tags never match.
+    let versions = split_tags(tags)
+        .filter(|tag| !tag.starts_with("W/"))
+        .filter_map(|tag| tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok())
+        .collect();
+    Some(IfMatch::Versions(versions))
+}
+
+fn split_tags(header: &str) -> impl Iterator<Item = &str> {
+    header.split(',').map(str::trim).filter(|tag| !tag.is_empty())
+}
+
+fn strip_weak(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn product(version: i64) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "widget".into(),
+            category: "tools".into(),
+            price_cents: 100,
+            stock: 1,
+            updated_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+            version,
+        }
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    fn versions(request: &HeaderMap) -> Option<Vec<i64>> {
+        match if_match(request)? {
+            IfMatch::Versions(versions) => Some(versions),
+            IfMatch::Any => None,
+        }
+    }
+
+    // --- page_etag ---------------------------------------------------------
+
+    #[test]
+    fn page_etag_is_deterministic() {
+        let page = vec![product(1), product(2)];
+        assert_eq!(page_etag(&page), page_etag(&page.clone()));
+        assert!(page_etag(&page).starts_with("\"p") && page_etag(&page).ends_with('"'));
+    }
+
+    #[test]
+    fn page_etag_changes_with_contents() {
+        let (a, b) = (product(1), product(1));
+        let page = vec![a.clone(), b.clone()];
+
+        let mut updated = page.clone();
+        updated[1].version += 1;
+        assert_ne!(page_etag(&page), page_etag(&updated));
+        assert_ne!(page_etag(&page), page_etag(&[b, a.clone()]), "reordered");
+        assert_ne!(page_etag(&page), page_etag(&[a]), "removed");
+        assert_ne!(page_etag(&page), page_etag(&[]));
+    }
+
+    // --- If-Match ----------------------------------------------------------
+
+    #[test]
+    fn if_match_absent() {
+        assert!(if_match(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn if_match_star() {
+        let request = headers(&[(header::IF_MATCH, " * ")]);
+        assert!(matches!(if_match(&request), Some(IfMatch::Any)));
+    }
+
+    #[test]
+    fn if_match_list() {
+        let request = headers(&[(header::IF_MATCH, r#""3", "7""#)]);
+        assert_eq!(versions(&request), Some(vec![3, 7]));
+    }
+
+    #[test]
+    fn if_match_ignores_weak_tags() {
+        let request = headers(&[(header::IF_MATCH, r#"W/"3", "4""#)]);
+        assert_eq!(versions(&request), Some(vec![4]));
+    }
+
+    #[test]
+    fn if_match_garbage_matches_nothing() {
+        // Present but unusable: a precondition that can never hold, not none.
+        for value in ["3", r#""abc""#, r#""p00ff""#, ","] {
+            let request = headers(&[(header::IF_MATCH, value)]);
+            assert_eq!(versions(&request), Some(vec![]), "If-Match: {value}");
+        }
+    }
+
+    // --- Conditional GET ---------------------------------------------------
+
+    #[test]
+    fn if_none_match_matches_current_etag() {
+        let request = headers(&[(header::IF_NONE_MATCH, r#""1", "2""#)]);
+        assert!(is_not_modified(&request, "\"2\"", None));
+        assert!(!is_not_modified(&request, "\"3\"", None));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let request = headers(&[(header::IF_NONE_MATCH, r#"W/"2""#)]);
+        assert!(is_not_modified(&request, "\"2\"", None));
+        assert!(is_not_modified(&headers(&[(header::IF_NONE_MATCH, "*")]), "\"2\"", None));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let modified = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let at = |at: DateTime<Utc>| headers(&[(header::IF_MODIFIED_SINCE, &http_date(at))]);
+
+        assert!(is_not_modified(&at(modified), "\"1\"", Some(modified)));
+        assert!(is_not_modified(&at(modified + Duration::hours(1)), "\"1\"", Some(modified)));
+        assert!(!is_not_modified(&at(modified - Duration::seconds(1)), "\"1\"", Some(modified)));
+        let garbage = headers(&[(header::IF_MODIFIED_SINCE, "yesterday")]);
+        assert!(!is_not_modified(&garbage, "\"1\"", Some(modified)));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let modified = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let date = http_date(modified + Duration::hours(1));
+
+        // The date alone would give a 304; the stale tag must win.
+        let request = headers(&[(header::IF_NONE_MATCH, "\"1\""), (header::IF_MODIFIED_SINCE, &date)]);
+        assert!(!is_not_modified(&request, "\"2\"", Some(modified)));
+
+        // And a matching tag wins over a date that would say "modified".
+        let date = http_date(modified - Duration::hours(1));
+        let request = headers(&[(header::IF_NONE_MATCH, "\"2\""), (header::IF_MODIFIED_SINCE, &date)]);
+        assert!(is_not_modified(&request, "\"2\"", Some(modified)));
+    }
+
+    #[test]
+    fn list_page_is_modified_after_a_deletion() {
+        let page = vec![product(1), product(1)];
+        let client_etag = page_etag(&page);
+        let newest = page.iter().map(|p| p.updated_at).max().unwrap();
+
+        // One product is deleted; the survivor's `updated_at` does not move.
+        let after = page_etag(&page[..1]);
+        let revalidate = headers(&[(header::IF_NONE_MATCH, &client_etag)]);
+        assert!(!is_not_modified(&revalidate, &after, None));
+
+        // List pages carry no Last-Modified, so a date alone never gives a 304.
+        let by_date = headers(&[(header::IF_MODIFIED_SINCE, &http_date(newest))]);
+        assert!(!is_not_modified(&by_date, &after, None));
+    }
+}
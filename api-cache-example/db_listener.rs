@@ -0,0 +1,114 @@
+/// db_listener.rs — Cache invalidation from Postgres LISTEN/NOTIFY
+///
+/// Design guarantees:
+///
+/// 1. WRITES OUTSIDE THE API INVALIDATE TOO
+///    - A trigger on `products` sends a NOTIFY for every committed row change,
+///      whoever made it: the API, a migration, a psql session, another
+///      service. Every replica LISTENs and drops the affected keys from L2
+///      and its own L1.
+///    - The API's own writes are therefore invalidated twice (once by the
+///      handler, once here). Invalidation is idempotent, so this costs a few
+///      extra cache operations, never correctness.
+///
+/// 2. MISSED NOTIFICATIONS ⇒ FULL FLUSH
+///    - Postgres only delivers notifications to sessions that are listening
+///      when they commit; anything sent while we are disconnected is lost.
+///      So after every (re)LISTEN the listener flushes L2 and the local L1
+///      before trusting notifications again. An undecodable notification
+///      flushes too.
+///
+/// 3. RECONNECTS
+///    - The listener runs in its own task on a dedicated connection and
+///      reconnects with a fixed back-off; the request path never waits on it.
+use std::time::Duration;
+
+use sqlx::postgres::PgListener;
+
+use crate::{cache::ProductCache, db::DbPool, invalidation_bus::Invalidation};
+
+/// Channel the `products` trigger notifies on.
+const CHANNEL: &str = "product_changes";
+
+/// Pause between attempts to re-establish the LISTEN connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Start the background task that applies database change notifications to
+/// `cache`. Runs for the lifetime of the process.
+pub fn spawn_listener(db: DbPool, cache: ProductCache) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen_and_apply(&db, &cache).await {
+                tracing::warn!(error = %e, "database change listener disconnected");
+            } else {
+                tracing::warn!("database change listener connection lost");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    })
+}
+
+/// One LISTEN session: returns when the connection is lost.
+async fn listen_and_apply(db: &DbPool, cache: &ProductCache) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(db).await?;
+    listener.listen(CHANNEL).await?;
+
+    // Anything committed before this point may have been missed.
+    tracing::info!(channel = CHANNEL, "listening for database changes — flushing caches");
+    cache.apply_external(&Invalidation::All).await;
+
+    // `try_recv` yields `None` when the connection drops. Reconnect ourselves
+    // rather than let it reconnect silently, so the flush above always runs.
+    while let Some(notification) = listener.try_recv().await? {
+        cache.apply_external(&decode(notification.payload())).await;
+    }
+
+    Ok(())
+}
+
+/// The invalidation a trigger payload asks for; a full flush if it cannot
+/// be decoded.
+fn decode(payload: &str) -> Invalidation {
+    serde_json::from_str(payload).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "undecodable change notification — flushing caches");
+        Invalidation::All
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    // Payloads as `json_build_object(..)::text` renders them.
+
+    #[test]
+    fn category_move_names_old_and_new_category() {
+        let id = Uuid::new_v4();
+        let payload = format!(r#"{{"kind" : "product", "id" : "{id}", "categories" : ["tools","garden"]}}"#);
+        assert_eq!(
+            decode(&payload),
+            Invalidation::Product { id, categories: Some(vec!["tools".into(), "garden".into()]) }
+        );
+    }
+
+    #[test]
+    fn delete_names_no_categories() {
+        let id = Uuid::new_v4();
+        let payload = format!(r#"{{"kind" : "product", "id" : "{id}", "categories" : []}}"#);
+        assert_eq!(decode(&payload), Invalidation::Product { id, categories: Some(vec![]) });
+    }
+
+    #[test]
+    fn truncate_flushes_everything() {
+        assert_eq!(decode(r#"{"kind" : "all"}"#), Invalidation::All);
+    }
+
+    #[test]
+    fn undecodable_payload_flushes_everything() {
+        for payload in ["", "not json", r#"{"kind" : "product"}"#, r#"{"kind" : "reindex"}"#] {
+            assert_eq!(decode(payload), Invalidation::All, "payload {payload:?}");
+        }
+    }
+}
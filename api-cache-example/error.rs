@@ -1,5 +1,7 @@
 /// error.rs — Centralised error type
-use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use std::time::Duration;
+
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
 use serde_json::json;
 use thiserror::Error;
 use uuid::Uuid;
@@ -17,6 +19,21 @@ pub enum AppError {
 
     #[error("invalid input: {0}")]
     BadRequest(String),
+
+    #[error("product {0} has changed since it was read")]
+    PreconditionFailed(Uuid),
+
+    #[error("product {id} was modified concurrently: expected version {expected}, found {actual}")]
+    Conflict { id: Uuid, expected: i64, actual: i64 },
+
+    #[error("missing or invalid admin token")]
+    Unauthorized,
+
+    #[error("too many requests; retry in {}s", retry_after_secs(*.retry_after))]
+    RateLimited { retry_after: Duration },
+
+    #[error("request body too large")]
+    PayloadTooLarge,
 }
 
 impl IntoResponse for AppError {
@@ -24,6 +41,21 @@ impl IntoResponse for AppError {
         let (status, message) = match &self {
             AppError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::BadRequest(m) => (StatusCode::BAD_REQUEST, m.clone()),
+            AppError::Conflict { .. } => (StatusCode::CONFLICT, self.to_string()),
+            AppError::PreconditionFailed(_) => (StatusCode::PRECONDITION_FAILED, self.to_string()),
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::RateLimited { retry_after } => {
+                let mut response = (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({ "error": self.to_string() })),
+                )
+                    .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, retry_after_secs(*retry_after).into());
+                return response;
+            }
+            AppError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
             _ => {
                 tracing::error!(error = %self, "internal error");
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error".into())
@@ -32,3 +64,92 @@ impl IntoResponse for AppError {
         (status, Json(json!({ "error": message }))).into_response()
     }
 }
+
+/// Response mapper for the whole router. The body limits (`DefaultBodyLimit`,
+/// `RequestBodyLimitLayer`) reject with a plain-text 413; give those the same
+/// JSON body as every other error.
+pub async fn json_payload_too_large(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return AppError::PayloadTooLarge.into_response();
+    }
+    response
+}
+
+/// `Retry-After` is in whole seconds; round up so a client that waits that
+/// long finds a token.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        extract::DefaultBodyLimit,
+        http::Request,
+        middleware,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+    use tower_http::limit::RequestBodyLimitLayer;
+
+    use super::*;
+
+    async fn echo(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        Json(body)
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/json", post(echo))
+            .route("/stream", post(|| async { "ok" }).layer(RequestBodyLimitLayer::new(16)))
+            .layer(DefaultBodyLimit::max(16))
+            .layer(middleware::map_response(json_payload_too_large))
+    }
+
+    async fn post_json(uri: &str, body: &str) -> Response {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        app().oneshot(request).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn retry_after_rounds_up() {
+        assert_eq!(retry_after_secs(Duration::from_secs(2)), 2);
+        assert_eq!(retry_after_secs(Duration::from_millis(2001)), 3);
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+    }
+
+    #[tokio::test]
+    async fn buffered_body_limit_is_json() {
+        let response = post_json("/json", r#"{"name": "far too long for the limit"}"#).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(json_body(response).await, json!({ "error": "request body too large" }));
+    }
+
+    #[tokio::test]
+    async fn streamed_body_limit_is_json() {
+        let response = post_json("/stream", r#"{"name": "far too long for the limit"}"#).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(json_body(response).await, json!({ "error": "request body too large" }));
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_untouched() {
+        let response = post_json("/json", "{}").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await, json!({}));
+    }
+}
@@ -6,17 +6,22 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use serde::Deserialize;
+use serde::Serialize;
 use uuid::Uuid;
 
 use crate::{
-    cache::ProductCache,
+    cache::{CacheStats, ProductCache, ProductChange},
+    conditional::{self, IfMatch},
     error::AppError,
+    listing::{ListQuery, ListingKey},
+    metrics::{self, Metrics},
     model::{CreateProduct, Product, UpdateProduct},
     db::DbPool,
+    outbox::{self, EventKind},
 };
 
 // ---------------------------------------------------------------------------
@@ -27,6 +32,25 @@ use crate::{
 pub struct AppState {
     pub cache: ProductCache,
     pub db: DbPool,
+    pub metrics: Arc<Metrics>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State over a pool that never connects, for tests of routes and
+    /// middleware that do not reach the database.
+    pub fn offline() -> Self {
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .expect("lazy pool");
+        let metrics = Arc::new(Metrics::new().expect("metrics"));
+        let cache = Arc::new(crate::cache::ProductCacheInner::new(
+            db.clone(),
+            crate::cache::CacheConfig::default(),
+            metrics.clone(),
+        ));
+        Self { cache, db, metrics }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -36,29 +60,50 @@ pub struct AppState {
 pub async fn get_product(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<Json<Arc<Product>>, AppError> {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     // Returns Arc<Product> — zero-copy clone from the cache to the response.
     let product = state.cache.get_product(id).await?;
-    Ok(Json(product))
+
+    let etag = conditional::product_etag(&product);
+    let validators = conditional::validator_headers(&etag, Some(product.updated_at));
+    if conditional::is_not_modified(&headers, &etag, Some(product.updated_at)) {
+        return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+    }
+    Ok((validators, Json(product)).into_response())
 }
 
 // ---------------------------------------------------------------------------
-// GET /products?category=widgets&page=0
+// GET /products?category=widgets&sort=-price&limit=50&cursor=..
 // ---------------------------------------------------------------------------
 
-#[derive(Deserialize)]
-pub struct ListQuery {
-    pub category: String,
-    pub page: Option<u32>,
+/// One page of a listing, plus the cursor for the next one.
+#[derive(Serialize)]
+pub struct ProductPage<'a> {
+    pub products: &'a [Product],
+    /// Pass back as `cursor` for the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 pub async fn list_products(
     State(state): State<AppState>,
     Query(q): Query<ListQuery>,
-) -> Result<Json<Arc<Vec<Product>>>, AppError> {
-    let page = q.page.unwrap_or(0);
-    let products = state.cache.get_products_by_category(&q.category, page).await?;
-    Ok(Json(products))
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let key = ListingKey::try_from(q)?;
+    let products = state.cache.get_products_by_category(key.clone()).await?;
+
+    // ETag only: removing a product changes the page without moving any
+    // remaining `updated_at`, so Last-Modified could wrongly answer 304.
+    let etag = conditional::page_etag(&products);
+    let validators = conditional::validator_headers(&etag, None);
+    if conditional::is_not_modified(&headers, &etag, None) {
+        return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+    }
+    let next_cursor = key.next_cursor(&products).map(|cursor| cursor.encode());
+    let page = ProductPage { products: &products, next_cursor };
+    Ok((validators, Json(page)).into_response())
 }
 
 // ---------------------------------------------------------------------------
@@ -73,6 +118,8 @@ pub async fn create_product(
         return Err(AppError::BadRequest("name is required".into()));
     }
 
+    // The change event commits (or not) with the row itself.
+    let mut tx = state.db.begin().await?;
     let product = sqlx::query_as::<_, Product>(
         "INSERT INTO products (id, name, category, price_cents, stock, updated_at)
          VALUES (gen_random_uuid(), $1, $2, $3, $4, NOW())
@@ -82,12 +129,14 @@ pub async fn create_product(
     .bind(&body.category)
     .bind(body.price_cents)
     .bind(body.stock)
-    .fetch_one(&state.db)
+    .fetch_one(&mut *tx)
     .await?;
+    outbox::record(&mut tx, EventKind::Created, &product).await?;
+    tx.commit().await?;
 
     // Invalidate the category list so the new item is visible on next read.
     // No need to pre-populate the by_id entry — cache-aside will fill it lazily.
-    state.cache.invalidate_product(product.id).await;
+    state.cache.invalidate_product(ProductChange::Created(&product)).await;
 
     Ok((StatusCode::CREATED, Json(product)))
 }
@@ -99,35 +148,118 @@ pub async fn create_product(
 pub async fn update_product(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(body): Json<UpdateProduct>,
-) -> Result<Json<Product>, AppError> {
+) -> Result<(HeaderMap, Json<Product>), AppError> {
     // Optimistic: fetch current row (may come from cache) before patching.
-    let current = state.cache.get_product(id).await?;
+    let mut current = state.cache.get_product(id).await?;
+
+    // If-Match is checked by the UPDATE itself (NULL: no precondition), not
+    // against `current`, which may be stale.
+    let if_match = conditional::if_match(&headers);
+    let if_match_versions = match &if_match {
+        Some(IfMatch::Versions(versions)) => Some(versions.clone()),
+        Some(IfMatch::Any) | None => None,
+    };
 
+    let expected_version = expected_version(body.version, if_match.is_some(), current.version);
+    if expected_version != Some(current.version) {
+        // Our cached copy is not (or may not be) the row being replaced.
+        // Re-read it so a category move is invalidated against where the
+        // product really was.
+        current = Arc::new(fetch_current(&state.db, id).await?);
+    }
+
+    let mut tx = state.db.begin().await?;
     let updated = sqlx::query_as::<_, Product>(
         "UPDATE products
             SET name        = COALESCE($2, name),
                 price_cents = COALESCE($3, price_cents),
                 stock       = COALESCE($4, stock),
-                updated_at  = NOW()
+                category    = COALESCE($5, category),
+                updated_at  = NOW(),
+                version     = version + 1
           WHERE id = $1
+            AND ($6::bigint IS NULL OR version = $6)
+            AND ($7::bigint[] IS NULL OR version = ANY($7))
          RETURNING *",
     )
     .bind(id)
-    .bind(body.name.as_deref().unwrap_or(&current.name))
-    .bind(body.price_cents.unwrap_or(current.price_cents))
-    .bind(body.stock.unwrap_or(current.stock))
-    .fetch_optional(&state.db)
-    .await?
-    .ok_or(AppError::NotFound(id))?;
+    .bind(body.name.as_deref())
+    .bind(body.price_cents)
+    .bind(body.stock)
+    .bind(body.category.as_deref())
+    .bind(expected_version)
+    .bind(&if_match_versions)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(updated) = updated else {
+        // No row: it is gone, If-Match failed (412), or the version moved on (409).
+        let Some(actual) = current_version(&state.db, id).await? else {
+            return Err(AppError::NotFound(id));
+        };
+        let Some(expected) = expected_version else {
+            // If-Match was the only precondition, and it failed.
+            return Err(AppError::PreconditionFailed(id));
+        };
+        if if_match_versions.is_some_and(|versions| !versions.contains(&actual)) {
+            return Err(AppError::PreconditionFailed(id));
+        }
+        if body.version.is_none() {
+            // The version we read came from a stale cache entry; drop it so
+            // the client's retry reads the current row.
+            state.cache.evict_product(id).await;
+        }
+        return Err(AppError::Conflict { id, expected, actual });
+    };
+    outbox::record(&mut tx, EventKind::Updated, &updated).await?;
+    tx.commit().await?;
 
     // REORDER SAFETY: invalidate AFTER the DB write commits.
     // Any concurrent reader that bypassed the cache between the write and this
     // invalidate will get either the old cached value or the new DB value —
     // both are acceptable in a cache-aside pattern.
-    state.cache.invalidate_product(id).await;
+    // `current` tells us where the product was, so a category move drops
+    // pages under both the old and the new category.
+    state
+        .cache
+        .invalidate_product(ProductChange::Updated { before: &current, after: &updated })
+        .await;
+
+    let etag = conditional::product_etag(&updated);
+    Ok((conditional::validator_headers(&etag, Some(updated.updated_at)), Json(updated)))
+}
+
+/// The version an update must find, or `None` for no version check.
+///
+/// The client's `version` always counts. Without one, an `If-Match` header is
+/// the client's precondition and nothing is added to it: the version we read
+/// may come from a stale cache, and must not turn a matching If-Match into a
+/// 409. Only a request with no precondition at all falls back to that read.
+fn expected_version(body_version: Option<i64>, has_if_match: bool, read_version: i64) -> Option<i64> {
+    match (body_version, has_if_match) {
+        (Some(version), _) => Some(version),
+        (None, true) => None,
+        (None, false) => Some(read_version),
+    }
+}
+
+/// The row as it is in the database right now, bypassing the cache.
+async fn fetch_current(db: &DbPool, id: Uuid) -> Result<Product, AppError> {
+    sqlx::query_as::<_, Product>("SELECT * FROM products WHERE id = $1")
+        .bind(id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(AppError::NotFound(id))
+}
 
-    Ok(Json(updated))
+async fn current_version(db: &DbPool, id: Uuid) -> Result<Option<i64>, AppError> {
+    let version = sqlx::query_scalar("SELECT version FROM products WHERE id = $1")
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+    Ok(version)
 }
 
 // ---------------------------------------------------------------------------
@@ -138,16 +270,64 @@ pub async fn delete_product(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
-    let rows = sqlx::query("DELETE FROM products WHERE id = $1")
+    // RETURNING gives us the category whose pages now shift, and the
+    // last state of the product for the event.
+    let mut tx = state.db.begin().await?;
+    let deleted = sqlx::query_as::<_, Product>("DELETE FROM products WHERE id = $1 RETURNING *")
         .bind(id)
-        .execute(&state.db)
+        .fetch_optional(&mut *tx)
         .await?
-        .rows_affected();
+        .ok_or(AppError::NotFound(id))?;
+    outbox::record(&mut tx, EventKind::Deleted, &deleted).await?;
+    tx.commit().await?;
+
+    state.cache.invalidate_product(ProductChange::Deleted(&deleted)).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ---------------------------------------------------------------------------
+// GET /cache/stats
+// ---------------------------------------------------------------------------
+
+/// Current weighted size of each cache, in estimated bytes and entries.
+pub async fn cache_stats(State(state): State<AppState>) -> Json<CacheStats> {
+    Json(state.cache.stats())
+}
 
-    if rows == 0 {
-        return Err(AppError::NotFound(id));
+// ---------------------------------------------------------------------------
+// GET /metrics
+// ---------------------------------------------------------------------------
+
+/// Cache and per-route HTTP metrics in Prometheus text format.
+pub async fn metrics(State(state): State<AppState>) -> Response {
+    match state.metrics.render(state.cache.stats()) {
+        Ok(body) => ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], body).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to render metrics");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
     }
+}
 
-    state.cache.invalidate_product(id).await;
-    Ok(StatusCode::NO_CONTENT)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_version_is_always_checked() {
+        assert_eq!(expected_version(Some(4), false, 7), Some(4));
+        assert_eq!(expected_version(Some(4), true, 7), Some(4));
+    }
+
+    #[test]
+    fn stale_cache_with_matching_if_match_checks_only_if_match() {
+        // Cache says version 3, the row (and the client's ETag) is at 5. The
+        // UPDATE must not also require version 3, or it would 409 instead of 200.
+        assert_eq!(expected_version(None, true, 3), None);
+    }
+
+    #[test]
+    fn no_precondition_falls_back_to_the_read_version() {
+        assert_eq!(expected_version(None, false, 3), Some(3));
+    }
 }
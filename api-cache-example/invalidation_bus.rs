@@ -0,0 +1,290 @@
+/// invalidation_bus.rs — Cross-instance cache invalidation over Redis pub/sub
+///
+/// Design guarantees:
+///
+/// 1. EVERY REPLICA SEES EVERY WRITE
+///    - Each invalidation applied locally is also published on a Redis
+///      channel. Every other instance subscribes and drops the same keys from
+///      its own L1, so a PATCH on one replica is not masked by stale entries
+///      on the others for up to `time_to_live`.
+///
+/// 2. MISSED MESSAGES ⇒ FULL FLUSH
+///    - Redis pub/sub is fire-and-forget: anything published while we are
+///      disconnected is lost. So after every (re)subscribe the subscriber
+///      flushes the whole local cache before trusting the bus again.
+///    - Each publisher numbers its messages. A gap in one origin's sequence
+///      (e.g. its publish failed, or the message was dropped) also triggers a
+///      full flush. So does an origin's first message after a subscribe if
+///      it is not that origin's message 1: its messages between our subscribe
+///      and this one may have been lost, and nothing else would show it.
+///
+/// 3. RECONNECTS
+///    - The subscriber runs in its own task and re-subscribes with a fixed
+///      back-off whenever the connection drops; the request path never waits
+///      on it. Publishing uses a `ConnectionManager`, which reconnects on its
+///      own.
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::cache::ProductCache;
+
+/// Pause between attempts to re-establish the subscription.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// ---------------------------------------------------------------------------
+// Wire format
+// ---------------------------------------------------------------------------
+
+/// What to drop from the cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Invalidation {
+    /// One product, the pages holding it, and every page of `categories`.
+    /// `None` (messages from older instances) means pages of all categories.
+    Product {
+        id: Uuid,
+        #[serde(default)]
+        categories: Option<Vec<String>>,
+    },
+    /// A batch of products written together (bulk import): each product,
+    /// the pages holding them, and every page of `categories`.
+    Products { ids: Vec<Uuid>, categories: Vec<String> },
+    /// Every page of one category (admin eviction). Instances that predate
+    /// this variant cannot decode it and fall back to a full flush.
+    Category { category: String },
+    /// Everything, e.g. after a bulk import.
+    All,
+}
+
+/// One message on the channel.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    /// Instance that published the message; it has already applied it.
+    origin: Uuid,
+    /// Per-origin sequence number, starting at 1, used to detect gaps.
+    seq: u64,
+    #[serde(flatten)]
+    invalidation: Invalidation,
+}
+
+// ---------------------------------------------------------------------------
+// Publisher
+// ---------------------------------------------------------------------------
+
+/// Publishing half of the bus, held by the cache.
+pub struct InvalidationBus {
+    conn: ConnectionManager,
+    channel: String,
+    instance_id: Uuid,
+    /// Last sequence number used. Held across the publish so messages leave
+    /// this instance in sequence order and subscribers never see a false gap.
+    seq: Mutex<u64>,
+}
+
+impl InvalidationBus {
+    pub async fn connect(redis_url: &str, channel: &str, instance_id: Uuid) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self {
+            conn,
+            channel: channel.to_owned(),
+            instance_id,
+            seq: Mutex::new(0),
+        })
+    }
+
+    /// Tell the other instances to apply `invalidation`.
+    ///
+    /// A failed publish is logged, not returned: the sequence number is still
+    /// consumed, so subscribers see the gap on our next message and flush.
+    pub async fn publish(&self, invalidation: Invalidation) {
+        let mut seq = self.seq.lock().await;
+        *seq += 1;
+        let envelope = Envelope {
+            origin: self.instance_id,
+            seq: *seq,
+            invalidation,
+        };
+        let payload = match serde_json::to_string(&envelope) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to encode invalidation");
+                return;
+            }
+        };
+
+        let mut conn = self.conn.clone();
+        if let Err(e) = conn.publish::<_, _, ()>(&self.channel, payload).await {
+            tracing::warn!(error = %e, seq = envelope.seq, "failed to publish invalidation");
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Subscriber
+// ---------------------------------------------------------------------------
+
+/// Start the background task that applies other instances' invalidations to
+/// `cache`. Runs for the lifetime of the process.
+pub fn spawn_subscriber(
+    redis_url: String,
+    channel: String,
+    instance_id: Uuid,
+    cache: ProductCache,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = subscribe_and_apply(&redis_url, &channel, instance_id, &cache).await {
+                tracing::warn!(error = %e, "invalidation bus disconnected");
+            } else {
+                tracing::warn!("invalidation bus stream ended");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    })
+}
+
+/// One subscription: returns when the connection is lost.
+async fn subscribe_and_apply(
+    redis_url: &str,
+    channel: &str,
+    instance_id: Uuid,
+    cache: &ProductCache,
+) -> redis::RedisResult<()> {
+    let client = redis::Client::open(redis_url)?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+
+    // Anything published before this point may have been missed.
+    tracing::info!(channel, "subscribed to invalidation bus — flushing local cache");
+    cache.flush_local().await;
+
+    let mut sequences = SeqTracker::default();
+    let mut messages = pubsub.into_on_message();
+
+    while let Some(msg) = messages.next().await {
+        let envelope: Envelope = match msg.get_payload::<String>().map(|p| serde_json::from_str(&p)) {
+            Ok(Ok(envelope)) => envelope,
+            Ok(Err(e)) => {
+                tracing::warn!(error = %e, "undecodable invalidation — flushing local cache");
+                cache.flush_local().await;
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "unreadable invalidation — flushing local cache");
+                cache.flush_local().await;
+                continue;
+            }
+        };
+
+        if envelope.origin == instance_id {
+            continue;
+        }
+
+        if let Some(expected) = sequences.missed(envelope.origin, envelope.seq) {
+            tracing::warn!(
+                origin = %envelope.origin,
+                expected,
+                got = envelope.seq,
+                "gap in invalidation sequence — flushing local cache"
+            );
+            cache.flush_local().await;
+            continue;
+        }
+
+        cache.apply_remote(&envelope.invalidation).await;
+    }
+
+    Ok(())
+}
+
+/// Last sequence number seen from each origin during one subscription.
+#[derive(Default)]
+struct SeqTracker {
+    last_seq: HashMap<Uuid, u64>,
+}
+
+impl SeqTracker {
+    /// Record `seq` from `origin`. Returns the sequence number expected
+    /// instead if messages before `seq` may have been missed.
+    ///
+    /// The first message from an origin is only trusted if it is its first
+    /// ever (1). A later one may follow messages lost since we subscribed;
+    /// the cost of assuming so is one flush per origin per subscribe.
+    fn missed(&mut self, origin: Uuid, seq: u64) -> Option<u64> {
+        let expected = self.last_seq.insert(origin, seq).map_or(1, |prev| prev + 1);
+        (seq > expected).then_some(expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_messages_are_trusted() {
+        let origin = Uuid::new_v4();
+        let mut sequences = SeqTracker::default();
+        for seq in 1..=5 {
+            assert_eq!(sequences.missed(origin, seq), None);
+        }
+    }
+
+    #[test]
+    fn gap_in_sequence_is_detected() {
+        let origin = Uuid::new_v4();
+        let mut sequences = SeqTracker::default();
+        assert_eq!(sequences.missed(origin, 1), None);
+        assert_eq!(sequences.missed(origin, 2), None);
+        assert_eq!(sequences.missed(origin, 5), Some(3));
+        // Back in step after the flush.
+        assert_eq!(sequences.missed(origin, 6), None);
+    }
+
+    #[test]
+    fn first_message_after_subscribe_must_be_seq_one() {
+        let origin = Uuid::new_v4();
+        let mut sequences = SeqTracker::default();
+        // Joined mid-stream: messages 1..=9 may include ones sent after we
+        // subscribed and lost.
+        assert_eq!(sequences.missed(origin, 10), Some(1));
+        assert_eq!(sequences.missed(origin, 11), None);
+    }
+
+    #[test]
+    fn origins_are_tracked_independently() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut sequences = SeqTracker::default();
+        assert_eq!(sequences.missed(a, 1), None);
+        assert_eq!(sequences.missed(b, 1), None);
+        assert_eq!(sequences.missed(a, 2), None);
+        assert_eq!(sequences.missed(b, 3), Some(2));
+    }
+
+    #[test]
+    fn envelope_wire_format_is_flat() {
+        let envelope = Envelope {
+            origin: Uuid::nil(),
+            seq: 7,
+            invalidation: Invalidation::Category { category: "widgets".into() },
+        };
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "origin": Uuid::nil(),
+                "seq": 7,
+                "kind": "category",
+                "category": "widgets",
+            })
+        );
+    }
+}
@@ -0,0 +1,519 @@
+/// l2_cache.rs — Optional shared Redis tier behind the in-process moka caches
+///
+/// Design guarantees:
+///
+/// 1. WARM REPLICAS
+///    - Every replica of the API reads through the same Redis, so a freshly
+///      deployed instance fills its L1 from L2 instead of from Postgres.
+///
+/// 2. BOUNDED STALENESS
+///    - Entries are serialized as JSON and written with `SET .. PX`, so every
+///      L2 entry carries its own TTL and disappears even if no one invalidates it.
+///
+/// 3. DEGRADE, DON'T FAIL
+///    - L2 is an optimisation, never the source of truth. Any Redis error is
+///      logged and treated as a miss (reads) or ignored (writes); the request
+///      falls through to the database.
+///
+/// 4. GROUP INVALIDATION WITHOUT SCAN
+///    - Product and category page keys are recorded in a Redis set per kind as
+///      they are written, so a whole kind can be dropped atomically by one Lua
+///      script instead of a `SCAN` over the keyspace.
+///    - Each category page key is also recorded in a set per category and in
+///      a set per product it lists. A write drops just the pages it affects,
+///      whichever replica wrote them.
+///
+/// 5. NO STALE WRITE-BACK
+///    - A DB result is only written back if nothing it depends on was
+///      invalidated since the L2 miss. The miss returns a fill token (Redis
+///      server time); every invalidation stamps a marker per scope it drops
+///      (a product, a category, all pages, everything) with the same clock.
+///      The fill script refuses to write if any marker the entry depends on
+///      is as new as the token — for a page, that includes every product it
+///      lists. So a load that read the DB before a write committed can never
+///      put the old value back after that write's invalidation.
+///    - Markers live for one L2 TTL; a load slower than that could still
+///      write back, and its entry would then expire within one TTL anyway.
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+use crate::{listing::ListingKey, model::Product};
+
+/// Read KEYS[1] together with the server time, which is the fill token.
+const READ_SCRIPT: &str = r#"
+    local time = redis.call('TIME')
+    local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+    return {redis.call('GET', KEYS[1]), now_ms}
+"#;
+
+/// Write KEYS[1] unless one of the invalidation markers is as new as the token.
+/// KEYS[2 .. n+1]: markers, then the sets to record KEYS[1] in.
+/// ARGV: value, TTL ms, fill token, n. Returns 1 if written, 0 if not.
+const FILL_SCRIPT: &str = r#"
+    local since = tonumber(ARGV[3])
+    local markers = tonumber(ARGV[4])
+    for i = 2, markers + 1 do
+        if tonumber(redis.call('GET', KEYS[i]) or '-1') >= since then
+            return 0
+        end
+    end
+
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    for i = markers + 2, #KEYS do
+        -- Every tracked key expires within one TTL, so the index can too.
+        redis.call('SADD', KEYS[i], KEYS[1])
+        redis.call('PEXPIRE', KEYS[i], ARGV[2])
+    end
+    return 1
+"#;
+
+/// Delete entries and stamp invalidation markers with the server time.
+/// KEYS: entry keys, then sets whose members (and themselves) are deleted,
+/// then markers. ARGV: number of entry keys, number of sets, marker TTL ms.
+/// Returns the number of keys deleted.
+const INVALIDATE_SCRIPT: &str = r#"
+    local entries = tonumber(ARGV[1])
+    local sets = tonumber(ARGV[2])
+    local time = redis.call('TIME')
+    local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+    local deleted = 0
+    for i = 1, entries do
+        deleted = deleted + redis.call('DEL', KEYS[i])
+    end
+    for i = entries + 1, entries + sets do
+        local keys = redis.call('SMEMBERS', KEYS[i])
+        for j = 1, #keys do
+            redis.call('DEL', keys[j])
+        end
+        redis.call('DEL', KEYS[i])
+        deleted = deleted + #keys
+    end
+    for i = entries + sets + 1, #KEYS do
+        redis.call('SET', KEYS[i], now_ms, 'PX', ARGV[3])
+    end
+    return deleted
+"#;
+
+// ---------------------------------------------------------------------------
+// L2 handle
+// ---------------------------------------------------------------------------
+
+/// Outcome of an L2 read.
+pub enum L2Read<T> {
+    Hit(T),
+    /// Not in L2 (or undecodable). The token allows writing the DB result
+    /// back with `fill_*`; `None` if Redis could not be reached.
+    Miss(Option<FillToken>),
+}
+
+/// Redis server time, in ms, of the read that missed. A fill is refused if
+/// anything it depends on was invalidated at or after this time.
+#[derive(Debug, Clone, Copy)]
+pub struct FillToken(i64);
+
+/// Cheap to clone: `ConnectionManager` multiplexes one reconnecting connection.
+#[derive(Clone)]
+pub struct L2Cache {
+    conn: ConnectionManager,
+    /// Namespace for every key, so several apps can share one Redis.
+    prefix: String,
+    time_to_live: Duration,
+}
+
+impl L2Cache {
+    pub async fn connect(redis_url: &str, prefix: &str, time_to_live: Duration) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self {
+            conn,
+            prefix: prefix.to_owned(),
+            time_to_live,
+        })
+    }
+
+    // -----------------------------------------------------------------------
+    // Keys
+    // -----------------------------------------------------------------------
+
+    pub fn product_key(&self, id: Uuid) -> String {
+        format!("{}:product:{id}", self.prefix)
+    }
+
+    /// Every listing parameter is part of the key (as JSON), so distinct
+    /// queries never share an L2 entry.
+    pub fn category_page_key(&self, key: &ListingKey) -> String {
+        let params = serde_json::to_string(key).unwrap_or_default();
+        format!("{}:category:{}:{params}", self.prefix, key.category)
+    }
+
+    /// Set of every product key currently written to L2.
+    fn product_index_key(&self) -> String {
+        format!("{}:product_keys", self.prefix)
+    }
+
+    /// Set of every category page key currently written to L2.
+    fn category_index_key(&self) -> String {
+        format!("{}:category_keys", self.prefix)
+    }
+
+    /// Set of the page keys of one category.
+    fn pages_in_category_key(&self, category: &str) -> String {
+        format!("{}:category_pages:{category}", self.prefix)
+    }
+
+    /// Set of the page keys listing one product.
+    fn pages_containing_key(&self, id: Uuid) -> String {
+        format!("{}:product_pages:{id}", self.prefix)
+    }
+
+    /// When a scope (`all`, `pages`, `category:{c}`, `product:{id}`) was
+    /// last invalidated.
+    fn marker_key(&self, scope: &str) -> String {
+        format!("{}:invalidated:{scope}", self.prefix)
+    }
+
+    // -----------------------------------------------------------------------
+    // Read / write
+    // -----------------------------------------------------------------------
+
+    /// A hit, or a miss with the token to fill the key with. Redis errors
+    /// and entries that no longer deserialize (e.g. written by an older
+    /// version of `Product`) count as misses.
+    pub async fn read<T: DeserializeOwned>(&self, key: &str) -> L2Read<T> {
+        let mut conn = self.conn.clone();
+        let (raw, now_ms): (Option<String>, i64) =
+            match redis::Script::new(READ_SCRIPT).key(key).invoke_async(&mut conn).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    tracing::warn!(key, error = %e, "L2 read failed — falling through to DB");
+                    return L2Read::Miss(None);
+                }
+            };
+        let token = Some(FillToken(now_ms));
+
+        let Some(raw) = raw else {
+            return L2Read::Miss(token);
+        };
+        match serde_json::from_str(&raw) {
+            Ok(value) => {
+                tracing::debug!(key, "L2 hit");
+                L2Read::Hit(value)
+            }
+            Err(e) => {
+                tracing::warn!(key, error = %e, "L2 entry undecodable — treating as miss");
+                L2Read::Miss(token)
+            }
+        }
+    }
+
+    /// Write back a product read from the DB after the miss that issued
+    /// `token`. Returns whether it was written.
+    pub async fn fill_product(&self, product: &Product, token: FillToken) -> bool {
+        let markers = [self.marker_key("all"), self.marker_key(&format!("product:{}", product.id))];
+        let indexes = [self.product_index_key()];
+        self.fill(&self.product_key(product.id), product, token, &markers, &indexes).await
+    }
+
+    /// As `fill_product`, for a page. Records it under its category and
+    /// under every product it lists, so `invalidate_products` can find it.
+    pub async fn fill_category_page(&self, page: &ListingKey, products: &[Product], token: FillToken) -> bool {
+        let mut markers = vec![
+            self.marker_key("all"),
+            self.marker_key("pages"),
+            self.marker_key(&format!("category:{}", page.category)),
+        ];
+        markers.extend(products.iter().map(|product| self.marker_key(&format!("product:{}", product.id))));
+        let mut indexes = vec![self.category_index_key(), self.pages_in_category_key(&page.category)];
+        indexes.extend(products.iter().map(|product| self.pages_containing_key(product.id)));
+
+        self.fill(&self.category_page_key(page), &products, token, &markers, &indexes).await
+    }
+
+    async fn fill<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        token: FillToken,
+        markers: &[String],
+        indexes: &[String],
+    ) -> bool {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<u8> = async {
+            let json = serde_json::to_string(value)
+                .map_err(|e| redis::RedisError::from((redis::ErrorKind::TypeError, "serialize", e.to_string())))?;
+            redis::Script::new(FILL_SCRIPT)
+                .key(key)
+                .key(markers)
+                .key(indexes)
+                .arg(json)
+                .arg(self.ttl_ms())
+                .arg(token.0)
+                .arg(markers.len())
+                .invoke_async(&mut conn)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(1) => true,
+            Ok(_) => {
+                tracing::debug!(key, "L2 write skipped — invalidated since the read");
+                false
+            }
+            Err(e) => {
+                tracing::warn!(key, error = %e, "L2 write failed");
+                false
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Invalidation
+    // -----------------------------------------------------------------------
+
+    /// Drop products `ids`, the pages listing any of them, and every page of
+    /// `categories`, in one round trip.
+    pub async fn invalidate_products(&self, ids: &[Uuid], categories: &[String]) {
+        if ids.is_empty() && categories.is_empty() {
+            return;
+        }
+        let entries: Vec<String> = ids.iter().map(|id| self.product_key(*id)).collect();
+        let sets: Vec<String> = ids
+            .iter()
+            .map(|id| self.pages_containing_key(*id))
+            .chain(categories.iter().map(|category| self.pages_in_category_key(category)))
+            .collect();
+        let markers: Vec<String> = ids
+            .iter()
+            .map(|id| self.marker_key(&format!("product:{id}")))
+            .chain(categories.iter().map(|category| self.marker_key(&format!("category:{category}"))))
+            .collect();
+        self.invalidate(&entries, &sets, &markers).await;
+    }
+
+    /// Drop every category page of every category.
+    pub async fn invalidate_category_pages(&self) {
+        self.invalidate(&[], &[self.category_index_key()], &[self.marker_key("pages")]).await;
+    }
+
+    /// Drop every product and category page entry this app has written.
+    pub async fn invalidate_all(&self) {
+        let sets = [self.product_index_key(), self.category_index_key()];
+        self.invalidate(&[], &sets, &[self.marker_key("all")]).await;
+    }
+
+    async fn invalidate(&self, entries: &[String], sets: &[String], markers: &[String]) {
+        let mut conn = self.conn.clone();
+        let result: redis::RedisResult<u64> = redis::Script::new(INVALIDATE_SCRIPT)
+            .key(entries)
+            .key(sets)
+            .key(markers)
+            .arg(entries.len())
+            .arg(sets.len())
+            .arg(self.ttl_ms())
+            .invoke_async(&mut conn)
+            .await;
+
+        if let Err(e) = result {
+            tracing::warn!(?entries, ?sets, error = %e, "L2 invalidate failed");
+        }
+    }
+
+    fn ttl_ms(&self) -> u64 {
+        self.time_to_live.as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Need a Redis at `REDIS_URL`: `cargo test -- --ignored`.
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use redis::AsyncCommands;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+    use crate::{
+        cache::{CacheConfig, ProductCacheInner, ProductChange},
+        metrics::Metrics,
+    };
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    /// A fresh namespace per test, so tests never see each other's keys.
+    async fn l2() -> L2Cache {
+        let prefix = format!("l2-test-{}", Uuid::new_v4());
+        L2Cache::connect(REDIS_URL, &prefix, Duration::from_secs(60)).await.unwrap()
+    }
+
+    fn product(category: &str) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "widget".into(),
+            category: category.to_owned(),
+            price_cents: 100,
+            stock: 1,
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    async fn token(l2: &L2Cache) -> FillToken {
+        match l2.read::<Product>(&l2.product_key(Uuid::new_v4())).await {
+            L2Read::Miss(Some(token)) => token,
+            _ => panic!("an unknown key must miss with a token"),
+        }
+    }
+
+    async fn exists(l2: &L2Cache, key: &str) -> bool {
+        l2.conn.clone().exists(key).await.unwrap()
+    }
+
+    async fn members(l2: &L2Cache, set: &str) -> Vec<String> {
+        l2.conn.clone().smembers(set).await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_key_layout_and_ttl() {
+        let l2 = l2().await;
+        let p = product("tools");
+        assert_eq!(l2.product_key(p.id), format!("{}:product:{}", l2.prefix, p.id));
+        let page_key = l2.category_page_key(&ListingKey::first_page("tools"));
+        assert!(page_key.starts_with(&format!("{}:category:tools:{{", l2.prefix)));
+
+        assert!(l2.fill_product(&p, token(&l2).await).await);
+        let ttl: i64 = l2.conn.clone().pttl(l2.product_key(p.id)).await.unwrap();
+        assert!(ttl > 0 && ttl <= 60_000, "ttl {ttl}");
+        assert_eq!(members(&l2, &l2.product_index_key()).await, [l2.product_key(p.id)]);
+
+        let L2Read::Hit(read) = l2.read::<Product>(&l2.product_key(p.id)).await else {
+            panic!("filled product must be read back");
+        };
+        assert_eq!(read.id, p.id);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_page_is_tracked_per_category_and_product() {
+        let l2 = l2().await;
+        let (a, b) = (product("tools"), product("tools"));
+        let page = ListingKey::first_page("tools");
+        let key = l2.category_page_key(&page);
+
+        assert!(l2.fill_category_page(&page, &[a.clone(), b.clone()], token(&l2).await).await);
+        assert_eq!(members(&l2, &l2.category_index_key()).await, vec![key.clone()]);
+        assert_eq!(members(&l2, &l2.pages_in_category_key("tools")).await, vec![key.clone()]);
+        assert_eq!(members(&l2, &l2.pages_containing_key(a.id)).await, vec![key.clone()]);
+        assert_eq!(members(&l2, &l2.pages_containing_key(b.id)).await, [key]);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_invalidation_drops_only_tracked_pages() {
+        let l2 = l2().await;
+        let (a, b) = (product("tools"), product("garden"));
+        let tools = ListingKey::first_page("tools");
+        let garden = ListingKey::first_page("garden");
+        l2.fill_category_page(&tools, std::slice::from_ref(&a), token(&l2).await).await;
+        l2.fill_category_page(&garden, std::slice::from_ref(&b), token(&l2).await).await;
+        l2.fill_product(&a, token(&l2).await).await;
+
+        // Dropping `a` takes its product key and the page listing it.
+        l2.invalidate_products(&[a.id], &[]).await;
+        assert!(!exists(&l2, &l2.product_key(a.id)).await);
+        assert!(!exists(&l2, &l2.category_page_key(&tools)).await);
+        assert!(!exists(&l2, &l2.pages_containing_key(a.id)).await);
+        assert!(exists(&l2, &l2.category_page_key(&garden)).await);
+
+        // Dropping a category takes all of its pages.
+        l2.invalidate_products(&[], &["garden".into()]).await;
+        assert!(!exists(&l2, &l2.category_page_key(&garden)).await);
+        assert!(!exists(&l2, &l2.pages_in_category_key("garden")).await);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_invalidate_all() {
+        let l2 = l2().await;
+        let p = product("tools");
+        let page = ListingKey::first_page("tools");
+        l2.fill_product(&p, token(&l2).await).await;
+        l2.fill_category_page(&page, std::slice::from_ref(&p), token(&l2).await).await;
+
+        l2.invalidate_all().await;
+        assert!(!exists(&l2, &l2.product_key(p.id)).await);
+        assert!(!exists(&l2, &l2.category_page_key(&page)).await);
+        assert!(!exists(&l2, &l2.product_index_key()).await);
+        assert!(!exists(&l2, &l2.category_index_key()).await);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_fill_after_invalidation_is_refused() {
+        let l2 = l2().await;
+        let p = product("tools");
+        let page = ListingKey::first_page("tools");
+
+        // The load missed, then a write committed and invalidated.
+        let before = token(&l2).await;
+        l2.invalidate_products(&[p.id], &[]).await;
+        assert!(!l2.fill_product(&p, before).await);
+        assert!(!exists(&l2, &l2.product_key(p.id)).await);
+        // The page lists the product, so it is refused too.
+        assert!(!l2.fill_category_page(&page, std::slice::from_ref(&p), before).await);
+
+        let before = token(&l2).await;
+        l2.invalidate_products(&[], &["tools".into()]).await;
+        assert!(!l2.fill_category_page(&page, &[], before).await);
+
+        let before = token(&l2).await;
+        l2.invalidate_category_pages().await;
+        assert!(!l2.fill_category_page(&page, &[], before).await);
+        assert!(l2.fill_product(&p, before).await, "pages only: products unaffected");
+
+        let before = token(&l2).await;
+        l2.invalidate_all().await;
+        assert!(!l2.fill_product(&p, before).await);
+
+        // Unrelated invalidations do not block a fill.
+        let before = token(&l2).await;
+        l2.invalidate_products(&[Uuid::new_v4()], &["garden".into()]).await;
+        assert!(l2.fill_category_page(&page, std::slice::from_ref(&p), before).await);
+        assert!(l2.fill_product(&p, before).await);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_lookup_order_l1_then_l2_then_db() {
+        let l2 = l2().await;
+        // Nothing listens here: any DB fetch fails fast.
+        let db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy("postgres://127.0.0.1:1/unused")
+            .unwrap();
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let cache = Arc::new(ProductCacheInner::new(db, CacheConfig::default(), metrics).with_l2(l2.clone()));
+
+        let p = product("tools");
+        l2.fill_product(&p, token(&l2).await).await;
+
+        // L1 miss, L2 hit: no DB needed.
+        assert_eq!(cache.get_product(p.id).await.unwrap().id, p.id);
+
+        // Gone from L2, still in L1.
+        l2.conn.clone().del::<_, ()>(l2.product_key(p.id)).await.unwrap();
+        assert_eq!(cache.get_product(p.id).await.unwrap().id, p.id);
+
+        // Gone from both: the lookup reaches the (unreachable) DB.
+        l2.fill_product(&p, token(&l2).await).await;
+        cache.invalidate_product(ProductChange::Deleted(&p)).await;
+        assert!(!exists(&l2, &l2.product_key(p.id)).await);
+        assert!(cache.get_product(p.id).await.is_err());
+    }
+}
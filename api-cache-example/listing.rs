@@ -0,0 +1,380 @@
+/// listing.rs — Query parameters, cursors and cache keys for GET /products
+///
+/// Design guarantees:
+///
+/// 1. STABLE PAGES UNDER WRITES
+///    - Pages are cut by keyset (`WHERE (name, id) > cursor`), not OFFSET.
+///      Inserting or deleting a row never shifts rows across later page
+///      boundaries, and deep pages cost the same as the first one.
+///
+/// 2. ONE CACHE ENTRY PER DISTINCT QUERY
+///    - `ListingKey` holds every parameter that changes the result — category,
+///      sort, page size, filters and cursor — so two different queries can
+///      never share a cached page.
+///
+/// 3. BOUNDED REQUESTS
+///    - Page sizes are capped at `MAX_LIMIT`, and malformed or mismatched
+///      cursors are rejected (400) instead of silently restarting at page one.
+use std::mem::size_of;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{error::AppError, model::Product};
+
+/// Page size when the client does not ask for one.
+pub const DEFAULT_LIMIT: u32 = 20;
+/// Largest page size a client may ask for.
+pub const MAX_LIMIT: u32 = 100;
+
+// ---------------------------------------------------------------------------
+// Sort order
+// ---------------------------------------------------------------------------
+
+/// Listing order. Every order breaks ties on `id`, so it is total and the
+/// keyset cursor is unambiguous.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    #[default]
+    NameAsc,
+    NameDesc,
+    PriceAsc,
+    PriceDesc,
+}
+
+impl Sort {
+    /// `name`, `-name`, `price` or `-price`.
+    fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            "name" => Ok(Sort::NameAsc),
+            "-name" => Ok(Sort::NameDesc),
+            "price" => Ok(Sort::PriceAsc),
+            "-price" => Ok(Sort::PriceDesc),
+            other => Err(AppError::BadRequest(format!(
+                "unknown sort {other:?}; expected name, -name, price or -price"
+            ))),
+        }
+    }
+
+    /// Column sorted on, before the `id` tie-breaker.
+    pub fn column(self) -> &'static str {
+        match self {
+            Sort::NameAsc | Sort::NameDesc => "name",
+            Sort::PriceAsc | Sort::PriceDesc => "price_cents",
+        }
+    }
+
+    pub fn descending(self) -> bool {
+        matches!(self, Sort::NameDesc | Sort::PriceDesc)
+    }
+
+    fn sort_value(self, product: &Product) -> SortValue {
+        match self {
+            Sort::NameAsc | Sort::NameDesc => SortValue::Name(product.name.clone()),
+            Sort::PriceAsc | Sort::PriceDesc => SortValue::Price(product.price_cents),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Cursor
+// ---------------------------------------------------------------------------
+
+/// The sort column's value in the last row of the previous page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortValue {
+    Name(String),
+    Price(i64),
+}
+
+/// Position after which the next page starts: `(sort value, id)` of the last
+/// row already returned.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cursor {
+    pub value: SortValue,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Opaque, URL-safe form handed to clients.
+    pub fn encode(&self) -> String {
+        // Serialising a plain enum + Uuid cannot fail.
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(token: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| AppError::BadRequest("invalid cursor".into()))
+    }
+
+    fn fits(&self, sort: Sort) -> bool {
+        matches!(
+            (&self.value, sort),
+            (SortValue::Name(_), Sort::NameAsc | Sort::NameDesc)
+                | (SortValue::Price(_), Sort::PriceAsc | Sort::PriceDesc)
+        )
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Cache key
+// ---------------------------------------------------------------------------
+
+/// One page of one listing query; the `by_category` cache key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ListingKey {
+    pub category: String,
+    pub sort: Sort,
+    pub limit: u32,
+    pub min_price_cents: Option<i64>,
+    pub max_price_cents: Option<i64>,
+    pub min_stock: Option<i32>,
+    pub max_stock: Option<i32>,
+    /// `None` for the first page.
+    pub after: Option<Cursor>,
+}
+
+impl ListingKey {
+    /// First page of `category` with default sort, size and no filters.
+    pub fn first_page(category: &str) -> Self {
+        Self {
+            category: category.to_owned(),
+            sort: Sort::default(),
+            limit: DEFAULT_LIMIT,
+            min_price_cents: None,
+            max_price_cents: None,
+            min_stock: None,
+            max_stock: None,
+            after: None,
+        }
+    }
+
+    /// Cursor for the page after `page`, or `None` if `page` is the last.
+    ///
+    /// A full page may be followed by an empty one; fetching `limit + 1` rows
+    /// would avoid that, but would also make every cached page one row larger.
+    pub fn next_cursor(&self, page: &[Product]) -> Option<Cursor> {
+        if page.len() < self.limit as usize {
+            return None;
+        }
+        let last = page.last()?;
+        Some(Cursor {
+            value: self.sort.sort_value(last),
+            id: last.id,
+        })
+    }
+
+    /// Key of the page after `page` with the same query, if there is one.
+    pub fn next_page(&self, page: &[Product]) -> Option<Self> {
+        let after = self.next_cursor(page)?;
+        Some(Self {
+            after: Some(after),
+            ..self.clone()
+        })
+    }
+
+    /// Heap bytes owned by the key, for the cache weigher.
+    pub fn heap_bytes(&self) -> usize {
+        let cursor = match &self.after {
+            Some(Cursor { value: SortValue::Name(name), .. }) => name.capacity(),
+            _ => 0,
+        };
+        self.category.capacity() + cursor
+    }
+
+    /// Full size of the key, stack and heap.
+    pub fn weight(&self) -> usize {
+        size_of::<Self>() + self.heap_bytes()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Query string
+// ---------------------------------------------------------------------------
+
+/// `GET /products?category=widgets&sort=-price&limit=50&min_price_cents=100&cursor=..`
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    pub category: String,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    pub sort: Option<String>,
+    pub min_price_cents: Option<i64>,
+    pub max_price_cents: Option<i64>,
+    pub min_stock: Option<i32>,
+    pub max_stock: Option<i32>,
+    /// Offset paging, no longer supported. Accepted only to be rejected, so
+    /// old clients get a 400 instead of page one over and over.
+    pub page: Option<String>,
+}
+
+impl TryFrom<ListQuery> for ListingKey {
+    type Error = AppError;
+
+    fn try_from(q: ListQuery) -> Result<Self, AppError> {
+        if q.page.is_some() {
+            return Err(AppError::BadRequest(
+                "page is not supported; pass the previous page's next_cursor as cursor".into(),
+            ));
+        }
+        let limit = q.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(AppError::BadRequest(format!("limit must be 1..={MAX_LIMIT}")));
+        }
+        if let (Some(min), Some(max)) = (q.min_price_cents, q.max_price_cents) {
+            if min > max {
+                return Err(AppError::BadRequest("min_price_cents is above max_price_cents".into()));
+            }
+        }
+        if let (Some(min), Some(max)) = (q.min_stock, q.max_stock) {
+            if min > max {
+                return Err(AppError::BadRequest("min_stock is above max_stock".into()));
+            }
+        }
+
+        let sort = q.sort.as_deref().map(Sort::parse).transpose()?.unwrap_or_default();
+        let after = q.cursor.as_deref().map(Cursor::decode).transpose()?;
+        if after.as_ref().is_some_and(|cursor| !cursor.fits(sort)) {
+            return Err(AppError::BadRequest("cursor does not belong to this sort order".into()));
+        }
+
+        Ok(Self {
+            category: q.category,
+            sort,
+            limit,
+            min_price_cents: q.min_price_cents,
+            max_price_cents: q.max_price_cents,
+            min_stock: q.min_stock,
+            max_stock: q.max_stock,
+            after,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn query(category: &str) -> ListQuery {
+        ListQuery {
+            category: category.to_owned(),
+            cursor: None,
+            limit: None,
+            sort: None,
+            min_price_cents: None,
+            max_price_cents: None,
+            min_stock: None,
+            max_stock: None,
+            page: None,
+        }
+    }
+
+    fn product(name: &str, price_cents: i64) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: name.to_owned(),
+            category: "tools".into(),
+            price_cents,
+            stock: 1,
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    fn rejected(q: ListQuery) -> bool {
+        matches!(ListingKey::try_from(q), Err(AppError::BadRequest(_)))
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for value in [SortValue::Name("Widget, \"deluxe\"".into()), SortValue::Price(-42)] {
+            let cursor = Cursor { value, id: Uuid::new_v4() };
+            let token = cursor.encode();
+            assert!(token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(Cursor::decode(&token).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        assert!(Cursor::decode("not a cursor!").is_err());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode(b"{}")).is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(ListingKey::try_from(query("tools")).unwrap(), ListingKey::first_page("tools"));
+    }
+
+    #[test]
+    fn limit_bounds() {
+        for (limit, ok) in [(0, false), (1, true), (MAX_LIMIT, true), (MAX_LIMIT + 1, false)] {
+            let q = ListQuery { limit: Some(limit), ..query("tools") };
+            assert_eq!(!rejected(q), ok, "limit {limit}");
+        }
+    }
+
+    #[test]
+    fn inverted_ranges_are_rejected() {
+        assert!(rejected(ListQuery { min_price_cents: Some(500), max_price_cents: Some(100), ..query("tools") }));
+        assert!(rejected(ListQuery { min_stock: Some(5), max_stock: Some(1), ..query("tools") }));
+        assert!(!rejected(ListQuery { min_price_cents: Some(100), max_price_cents: Some(100), ..query("tools") }));
+    }
+
+    #[test]
+    fn unknown_sort_is_rejected() {
+        assert!(rejected(ListQuery { sort: Some("stock".into()), ..query("tools") }));
+    }
+
+    #[test]
+    fn cursor_must_match_sort() {
+        let by_name = Cursor { value: SortValue::Name("a".into()), id: Uuid::new_v4() }.encode();
+        let by_price = Cursor { value: SortValue::Price(1), id: Uuid::new_v4() }.encode();
+
+        let q = |sort: &str, cursor: &str| ListQuery {
+            sort: Some(sort.into()),
+            cursor: Some(cursor.into()),
+            ..query("tools")
+        };
+        assert!(rejected(q("price", &by_name)));
+        assert!(rejected(q("-name", &by_price)));
+        assert!(!rejected(q("-name", &by_name)));
+        assert!(!rejected(q("-price", &by_price)));
+    }
+
+    #[test]
+    fn offset_page_is_rejected() {
+        let q = ListQuery { page: Some("2".into()), ..query("tools") };
+        let Err(AppError::BadRequest(message)) = ListingKey::try_from(q) else {
+            panic!("?page= must be rejected");
+        };
+        assert!(message.contains("cursor"));
+    }
+
+    #[test]
+    fn next_cursor_points_after_the_last_row() {
+        let key = ListingKey { limit: 2, sort: Sort::PriceDesc, ..ListingKey::first_page("tools") };
+        let page = [product("a", 300), product("b", 200)];
+
+        let cursor = key.next_cursor(&page).unwrap();
+        assert_eq!(cursor, Cursor { value: SortValue::Price(200), id: page[1].id });
+        assert_eq!(key.next_page(&page).unwrap().after, Some(cursor));
+    }
+
+    #[test]
+    fn no_next_cursor_on_the_last_page() {
+        let key = ListingKey { limit: 2, ..ListingKey::first_page("tools") };
+        assert_eq!(key.next_cursor(&[product("a", 1)]), None);
+        assert_eq!(key.next_cursor(&[]), None);
+        assert!(key.next_page(&[product("a", 1)]).is_none());
+    }
+}
@@ -1,23 +1,49 @@
 /// main.rs — Application entry point
 ///
 /// Wires together: DB pool → cache → Axum router → server
+mod admin;
+mod bulk;
 mod cache;
+mod conditional;
 mod db;
+mod db_listener;
 mod error;
 mod handlers;
+mod invalidation_bus;
+mod l2_cache;
+mod listing;
+mod metrics;
 mod model;
+mod outbox;
+mod page_index;
+mod rate_limit;
+mod refresh;
 
 use std::sync::Arc;
 
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{delete, get, patch, post},
     Router,
 };
-use tower_http::trace::TraceLayer;
+use tower_http::{limit::RequestBodyLimitLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use cache::{CacheConfig, ProductCacheInner};
 use handlers::AppState;
+use invalidation_bus::InvalidationBus;
+use l2_cache::L2Cache;
+use metrics::Metrics;
+use rate_limit::{RateLimitConfig, RateLimiter};
+
+/// Redis channel every replica publishes and subscribes to.
+const INVALIDATION_CHANNEL: &str = "api-cache-example:invalidations";
+
+/// Largest JSON request body (create / update); products are small.
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+/// Largest bulk import body. It is streamed, so this bounds work, not memory.
+const DEFAULT_MAX_IMPORT_BYTES: usize = 256 * 1024 * 1024;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -38,18 +64,116 @@ async fn main() -> anyhow::Result<()> {
 
     // --- Cache -------------------------------------------------------------
     // CacheConfig::default() is a safe starting point.
-    // Tune max_capacity and TTLs based on your load profile.
-    let cache = Arc::new(ProductCacheInner::new(db.clone(), CacheConfig::default()));
+    // Tune the byte budgets and TTLs based on your load profile.
+    let cache_cfg = CacheConfig::default();
+    let l2_time_to_live = cache_cfg.l2_time_to_live;
+    let metrics = Arc::new(Metrics::new()?);
+    let mut cache = ProductCacheInner::new(db.clone(), cache_cfg, metrics.clone());
+
+    // Optional shared L2 + invalidation bus: set REDIS_URL to let replicas
+    // warm each other and see each other's writes.
+    // An unreachable Redis at startup is not fatal — we just run L1-only.
+    let redis_url = std::env::var("REDIS_URL").ok();
+    let instance_id = uuid::Uuid::new_v4();
+    if let Some(redis_url) = &redis_url {
+        match L2Cache::connect(redis_url, "api-cache-example", l2_time_to_live).await {
+            Ok(l2) => {
+                tracing::info!("connected to Redis L2 cache");
+                cache = cache.with_l2(l2);
+            }
+            Err(e) => tracing::warn!(error = %e, "Redis L2 unavailable — running with L1 only"),
+        }
+        match InvalidationBus::connect(redis_url, INVALIDATION_CHANNEL, instance_id).await {
+            Ok(bus) => cache = cache.with_invalidation_bus(bus),
+            Err(e) => tracing::warn!(error = %e, "invalidation bus unavailable — local invalidation only"),
+        }
+    }
+    let cache = Arc::new(cache);
+
+    // --- Rate limiting -----------------------------------------------------
+    // Per-client token buckets, shared through Redis when it is configured.
+    // RATE_LIMIT_PER_SEC=0 switches limiting off.
+    let rate_limiter = match RateLimitConfig::from_env().map_err(anyhow::Error::msg)? {
+        Some(cfg) => {
+            let mut limiter = RateLimiter::new(cfg);
+            if let Some(redis_url) = &redis_url {
+                match rate_limit::connect_redis(redis_url).await {
+                    Ok(conn) => limiter = limiter.with_redis(conn, "api-cache-example"),
+                    Err(e) => tracing::warn!(error = %e, "Redis unavailable — rate limits are per replica"),
+                }
+            }
+            Some(Arc::new(limiter))
+        }
+        None => {
+            tracing::info!("RATE_LIMIT_PER_SEC=0 — rate limiting disabled");
+            None
+        }
+    };
+    let max_body = match std::env::var("MAX_BODY_BYTES") {
+        Ok(value) => value.parse()?,
+        Err(_) => DEFAULT_MAX_BODY_BYTES,
+    };
+    let max_import = match std::env::var("MAX_IMPORT_BYTES") {
+        Ok(value) => value.parse()?,
+        Err(_) => DEFAULT_MAX_IMPORT_BYTES,
+    };
+
+    // The subscriber reconnects on its own, so start it even if Redis is
+    // down right now.
+    if let Some(redis_url) = redis_url {
+        invalidation_bus::spawn_subscriber(redis_url, INVALIDATION_CHANNEL.into(), instance_id, cache.clone());
+    }
+
+    // Writes made outside this API (psql, migrations, other services) reach
+    // the cache through a NOTIFY trigger on `products`.
+    db_listener::spawn_listener(db.clone(), cache.clone());
+
+    // --- Outbox relay ------------------------------------------------------
+    // Writes always record change events; publishing them needs a sink.
+    // The relay takes an advisory lock per round, so every replica can run it.
+    match outbox::sink_from_env().await {
+        Ok(Some(sink)) => {
+            outbox::spawn_relay(db.clone(), sink, outbox::RelayConfig::default());
+        }
+        Ok(None) => tracing::warn!("OUTBOX_SINK not set — change events accumulate in product_outbox"),
+        Err(e) => anyhow::bail!("outbox sink: {e}"),
+    }
 
     // --- Router ------------------------------------------------------------
-    let state = AppState { cache, db };
+    let state = AppState { cache, db, metrics };
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/products",         get(handlers::list_products))
         .route("/products",         post(handlers::create_product))
+        // The import streams its body, so it needs a limit of its own.
+        .route("/products/import",  post(bulk::import_products).layer(RequestBodyLimitLayer::new(max_import)))
+        .route("/products/export",  get(bulk::export_products))
         .route("/products/:id",     get(handlers::get_product))
         .route("/products/:id",     patch(handlers::update_product))
-        .route("/products/:id",     delete(handlers::delete_product))
+        .route("/products/:id",     delete(handlers::delete_product));
+
+    // Rate limits cover the routes above only: monitoring and admin calls
+    // are never throttled.
+    if let Some(limiter) = rate_limiter {
+        app = app.route_layer(middleware::from_fn_with_state(limiter, rate_limit::limit));
+    }
+
+    app = app
+        .route("/cache/stats",      get(handlers::cache_stats))
+        .route("/metrics",          get(handlers::metrics));
+
+    // Cache admin routes exist only when a token is configured.
+    match std::env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => app = app.nest("/admin", admin::router(token)),
+        _ => tracing::info!("ADMIN_TOKEN not set — admin routes disabled"),
+    }
+
+    let app = app
+        // route_layer: runs after routing, so MatchedPath is available.
+        .route_layer(middleware::from_fn_with_state(state.clone(), metrics::track_requests))
+        // Caps every buffered body (JSON); the import's own limit is larger.
+        .layer(DefaultBodyLimit::max(max_body))
+        .layer(middleware::map_response(error::json_payload_too_large))
         .layer(TraceLayer::new_for_http())
         .with_state(state);
 
@@ -58,7 +182,8 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("listening on {addr}");
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Connect info gives the rate limiter each client's address.
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
 
     Ok(())
 }
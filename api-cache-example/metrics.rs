@@ -0,0 +1,358 @@
+/// metrics.rs — Prometheus instrumentation for the cache and the HTTP routes
+///
+/// Design guarantees:
+///
+/// 1. CHEAP ON THE HOT PATH
+///    - Counters and histograms are lock-free atomics; label lookups go
+///      through `with_label_values` on a handful of fixed label sets.
+///
+/// 2. BOUNDED CARDINALITY
+///    - Routes are labelled by their *matched pattern* (`/products/:id`),
+///      never the raw path, so a scan of random IDs cannot mint new series.
+///      Unmatched requests share a single `<unmatched>` route label.
+///
+/// 3. DERIVED VALUES ARE COMPUTED AT SCRAPE TIME
+///    - Weighted sizes come from `ProductCacheInner::stats()` and hit ratios
+///      from the lookup counters when `/metrics` is rendered, so there is
+///      nothing extra to keep in sync on every request.
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use moka::notification::RemovalCause;
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// `Content-Type` of the text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+use crate::{cache::CacheStats, handlers::AppState};
+
+// ---------------------------------------------------------------------------
+// Label values
+// ---------------------------------------------------------------------------
+
+/// Which in-process cache an event belongs to.
+#[derive(Debug, Clone, Copy)]
+pub enum CacheName {
+    ById,
+    ByCategory,
+    NotFound,
+}
+
+impl CacheName {
+    fn as_str(self) -> &'static str {
+        match self {
+            CacheName::ById => "by_id",
+            CacheName::ByCategory => "by_category",
+            CacheName::NotFound => "not_found",
+        }
+    }
+}
+
+/// How a cache read was answered.
+#[derive(Debug, Clone, Copy)]
+pub enum Lookup {
+    /// Value was already cached.
+    Hit,
+    /// This caller ran the initialiser (L2 / DB load).
+    Miss,
+    /// Another caller's initialiser was in flight; this one waited for it.
+    CoalescedWait,
+}
+
+impl Lookup {
+    /// `cached`: the key was present just before the lookup.
+    /// `loaded`: this caller's initialiser ran.
+    pub fn classify(cached: bool, loaded: bool) -> Self {
+        match (loaded, cached) {
+            (true, _) => Lookup::Miss,
+            (false, true) => Lookup::Hit,
+            (false, false) => Lookup::CoalescedWait,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Lookup::Hit => "hit",
+            Lookup::Miss => "miss",
+            Lookup::CoalescedWait => "coalesced_wait",
+        }
+    }
+}
+
+fn cause_label(cause: RemovalCause) -> &'static str {
+    match cause {
+        RemovalCause::Expired => "expired",
+        RemovalCause::Explicit => "explicit",
+        RemovalCause::Replaced => "replaced",
+        RemovalCause::Size => "size",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Registry
+// ---------------------------------------------------------------------------
+
+pub struct Metrics {
+    registry: Registry,
+    cache_lookups: IntCounterVec,
+    cache_evictions: IntCounterVec,
+    cache_hit_ratio: GaugeVec,
+    cache_weighted_bytes: IntGaugeVec,
+    cache_entries: IntGaugeVec,
+    db_fetch_seconds: HistogramVec,
+    http_requests: IntCounterVec,
+    http_request_seconds: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("product_api".into()), None)?;
+
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("cache_lookups_total", "Cache reads by cache and outcome (hit, miss, coalesced_wait)."),
+            &["cache", "result"],
+        )?;
+        let cache_evictions = IntCounterVec::new(
+            Opts::new("cache_evictions_total", "Entries removed from a cache, by cause."),
+            &["cache", "cause"],
+        )?;
+        let cache_hit_ratio = GaugeVec::new(
+            Opts::new("cache_hit_ratio", "Share of lookups answered without waiting on a load, since start."),
+            &["cache"],
+        )?;
+        let cache_weighted_bytes = IntGaugeVec::new(
+            Opts::new("cache_weighted_bytes", "Estimated bytes held by a cache."),
+            &["cache"],
+        )?;
+        let cache_entries = IntGaugeVec::new(
+            Opts::new("cache_entries", "Entries held by a cache."),
+            &["cache"],
+        )?;
+        let db_fetch_seconds = HistogramVec::new(
+            HistogramOpts::new("db_fetch_duration_seconds", "Latency of cache-fill queries against Postgres.")
+                .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+            &["query"],
+        )?;
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status."),
+            &["method", "route", "status"],
+        )?;
+        let http_request_seconds = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route and method."),
+            &["method", "route"],
+        )?;
+
+        registry.register(Box::new(cache_lookups.clone()))?;
+        registry.register(Box::new(cache_evictions.clone()))?;
+        registry.register(Box::new(cache_hit_ratio.clone()))?;
+        registry.register(Box::new(cache_weighted_bytes.clone()))?;
+        registry.register(Box::new(cache_entries.clone()))?;
+        registry.register(Box::new(db_fetch_seconds.clone()))?;
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_seconds.clone()))?;
+
+        Ok(Self {
+            registry,
+            cache_lookups,
+            cache_evictions,
+            cache_hit_ratio,
+            cache_weighted_bytes,
+            cache_entries,
+            db_fetch_seconds,
+            http_requests,
+            http_request_seconds,
+        })
+    }
+
+    // -----------------------------------------------------------------------
+    // Recording
+    // -----------------------------------------------------------------------
+
+    pub fn record_lookup(&self, cache: CacheName, lookup: Lookup) {
+        self.cache_lookups
+            .with_label_values(&[cache.as_str(), lookup.as_str()])
+            .inc();
+    }
+
+    /// Call from the cache's eviction listener.
+    pub fn record_eviction(&self, cache: CacheName, cause: RemovalCause) {
+        self.cache_evictions
+            .with_label_values(&[cache.as_str(), cause_label(cause)])
+            .inc();
+    }
+
+    /// Time one DB query; `query` is a fixed name such as "product".
+    pub fn record_db_fetch(&self, query: &'static str, started: Instant) {
+        self.db_fetch_seconds
+            .with_label_values(&[query])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    /// Hits over all lookups of `cache`; 0 before the first lookup.
+    fn hit_ratio(&self, cache: CacheName) -> f64 {
+        let count = |lookup: Lookup| {
+            self.cache_lookups
+                .with_label_values(&[cache.as_str(), lookup.as_str()])
+                .get() as f64
+        };
+        let hits = count(Lookup::Hit);
+        let total = hits + count(Lookup::Miss) + count(Lookup::CoalescedWait);
+        if total == 0.0 {
+            0.0
+        } else {
+            hits / total
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Rendering
+    // -----------------------------------------------------------------------
+
+    /// Everything in Prometheus text exposition format.
+    pub fn render(&self, stats: CacheStats) -> Result<String, prometheus::Error> {
+        let sizes = [
+            (CacheName::ById, stats.by_id_bytes, stats.by_id_entries),
+            (CacheName::ByCategory, stats.by_category_bytes, stats.by_category_entries),
+            (CacheName::NotFound, 0, stats.not_found_entries),
+        ];
+        for (cache, bytes, entries) in sizes {
+            self.cache_hit_ratio
+                .with_label_values(&[cache.as_str()])
+                .set(self.hit_ratio(cache));
+            // Gauges are i64; a cache never gets near that.
+            self.cache_weighted_bytes
+                .with_label_values(&[cache.as_str()])
+                .set(bytes as i64);
+            self.cache_entries
+                .with_label_values(&[cache.as_str()])
+                .set(entries as i64);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP
+// ---------------------------------------------------------------------------
+
+/// Middleware: count and time every request by its matched route.
+pub async fn track_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "<unmatched>".to_owned());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    let metrics = &state.metrics;
+    metrics
+        .http_request_seconds
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request as HttpRequest, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn classify_hit_and_miss() {
+        assert!(matches!(Lookup::classify(true, false), Lookup::Hit));
+        assert!(matches!(Lookup::classify(false, true), Lookup::Miss));
+    }
+
+    #[test]
+    fn classify_waiting_on_another_load() {
+        assert!(matches!(Lookup::classify(false, false), Lookup::CoalescedWait));
+    }
+
+    #[test]
+    fn classify_stale_entry() {
+        // A soft-TTL-stale entry is still served from cache: a hit. One that
+        // expired between the check and the read is reloaded: a miss.
+        assert!(matches!(Lookup::classify(true, false), Lookup::Hit));
+        assert!(matches!(Lookup::classify(true, true), Lookup::Miss));
+    }
+
+    #[test]
+    fn negative_hits_are_counted_under_not_found() {
+        let metrics = Metrics::new().unwrap();
+        metrics.record_lookup(CacheName::NotFound, Lookup::Hit);
+        metrics.record_lookup(CacheName::ById, Lookup::Miss);
+
+        let text = metrics.render(CacheStats::default()).unwrap();
+        assert!(text.contains(r#"product_api_cache_lookups_total{cache="not_found",result="hit"} 1"#));
+        assert!(text.contains(r#"product_api_cache_lookups_total{cache="by_id",result="miss"} 1"#));
+        assert!(text.contains(r#"product_api_cache_hit_ratio{cache="by_id"} 0"#));
+    }
+
+    #[test]
+    fn render_reports_cache_sizes() {
+        let metrics = Metrics::new().unwrap();
+        let stats = CacheStats {
+            by_id_bytes: 1234,
+            by_id_entries: 5,
+            by_category_bytes: 678,
+            by_category_entries: 2,
+            not_found_entries: 3,
+        };
+
+        let text = metrics.render(stats).unwrap();
+        for line in [
+            r#"product_api_cache_weighted_bytes{cache="by_id"} 1234"#,
+            r#"product_api_cache_entries{cache="by_id"} 5"#,
+            r#"product_api_cache_weighted_bytes{cache="by_category"} 678"#,
+            r#"product_api_cache_entries{cache="by_category"} 2"#,
+            r#"product_api_cache_entries{cache="not_found"} 3"#,
+        ] {
+            assert!(text.contains(line), "missing {line:?} in:\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn render_reports_requests_by_matched_route() {
+        let state = AppState::offline();
+        let metrics = state.metrics.clone();
+
+        let app = Router::new()
+            .route("/products/:id", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(state.clone(), track_requests))
+            .with_state(state);
+        for id in ["a", "b"] {
+            let request = HttpRequest::get(format!("/products/{id}")).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let text = metrics.render(CacheStats::default()).unwrap();
+        assert!(text.contains(
+            r#"product_api_http_requests_total{method="GET",route="/products/:id",status="200"} 2"#
+        ));
+        assert!(text.contains(
+            r#"product_api_http_request_duration_seconds_count{method="GET",route="/products/:id"} 2"#
+        ));
+        // Raw paths never become labels.
+        assert!(!text.contains("/products/a"));
+    }
+}
@@ -13,6 +13,8 @@ pub struct Product {
     pub price_cents: i64,
     pub stock: i32,
     pub updated_at: DateTime<Utc>,
+    /// Bumped by every update; the basis for optimistic concurrency.
+    pub version: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +28,10 @@ pub struct CreateProduct {
 #[derive(Debug, Deserialize)]
 pub struct UpdateProduct {
     pub name: Option<String>,
+    pub category: Option<String>,
     pub price_cents: Option<i64>,
     pub stock: Option<i32>,
+    /// The version the client's edit is based on. If omitted, the version
+    /// the server reads before updating is used.
+    pub version: Option<i64>,
 }
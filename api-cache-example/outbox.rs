@@ -0,0 +1,461 @@
+/// outbox.rs — Transactional outbox for product change events
+///
+/// Design guarantees:
+///
+/// 1. NO LOST OR PHANTOM EVENTS
+///    - Every product mutation inserts its event into `product_outbox` inside
+///      the same transaction as the write. The event exists if and only if
+///      the write committed.
+///
+/// 2. AT-LEAST-ONCE DELIVERY
+///    - The relay publishes an event first and marks it sent afterwards. A
+///      crash in between re-publishes it on the next run, so consumers must
+///      de-duplicate on the event `id`. An event is never marked sent unless
+///      the sink acknowledged it.
+///
+/// 3. IN ORDER PER PRODUCT
+///    - Events are published in `id` order by a single relay at a time: each
+///      round takes a Postgres advisory lock, so replicas do not race each
+///      other. Writes to one product serialise on its row lock, so its events
+///      get increasing ids. A failed publish stops the round, so nothing
+///      overtakes the failed event.
+///    - The lock is a session lock, not a transaction: no snapshot stays open
+///      while a slow broker is waited on, so vacuum is never held back. If
+///      the relay's connection drops mid-round the lock goes with it and
+///      another replica may re-publish the batch — covered by (2).
+///
+/// 4. PLUGGABLE SINK
+///    - The relay only knows `EventSink`. A logging sink is always available;
+///      RabbitMQ (`rabbitmq` feature) and Kafka (`kafka` feature) sinks are
+///      compiled in on demand.
+use std::{
+    error::Error as StdError,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use sqlx::{types::Json, FromRow, PgConnection};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db::DbPool, model::Product};
+
+/// Advisory lock key held by the relay that is currently publishing.
+const RELAY_LOCK_KEY: i64 = 0x0075_7462_6f78; // "outbox"
+/// How often sent events past retention are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl EventKind {
+    fn event_type(self) -> &'static str {
+        match self {
+            EventKind::Created => "product.created",
+            EventKind::Updated => "product.updated",
+            EventKind::Deleted => "product.deleted",
+        }
+    }
+}
+
+/// Write the change event for `product` as part of the caller's transaction.
+/// For a delete, `product` is the row as it was before removal.
+pub async fn record(conn: &mut PgConnection, kind: EventKind, product: &Product) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO product_outbox (product_id, event_type, payload) VALUES ($1, $2, $3)")
+        .bind(product.id)
+        .bind(kind.event_type())
+        .bind(Json(product))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// One outbox row, as handed to a sink. Serialises to the message body.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct OutboxEvent {
+    /// Unique and increasing; consumers de-duplicate on it.
+    pub id: i64,
+    pub product_id: Uuid,
+    pub event_type: String,
+    /// The product after the change (before it, for deletes).
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OutboxEvent {
+    /// JSON message body.
+    pub fn to_json(&self) -> Vec<u8> {
+        // Plain fields plus an already-valid JSON value: cannot fail.
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Sinks
+// ---------------------------------------------------------------------------
+
+pub type SinkError = Box<dyn StdError + Send + Sync>;
+
+/// Where the relay delivers events. `publish` must only return `Ok` once the
+/// broker has durably accepted the event.
+pub trait EventSink: Send + Sync + 'static {
+    fn publish<'a>(&'a self, event: &'a OutboxEvent) -> BoxFuture<'a, Result<(), SinkError>>;
+}
+
+/// Logs events instead of delivering them — for development, or to drain
+/// the outbox when no broker is wanted.
+pub struct LogSink;
+
+impl EventSink for LogSink {
+    fn publish<'a>(&'a self, event: &'a OutboxEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let message = String::from_utf8_lossy(&event.to_json()).into_owned();
+            tracing::info!(id = event.id, event_type = %event.event_type, %message, "outbox event");
+            Ok(())
+        })
+    }
+}
+
+/// Publishes to a RabbitMQ exchange with publisher confirms, routed by
+/// event type (`product.created`, …).
+#[cfg(feature = "rabbitmq")]
+pub struct RabbitMqSink {
+    channel: lapin::Channel,
+    exchange: String,
+}
+
+#[cfg(feature = "rabbitmq")]
+impl RabbitMqSink {
+    pub async fn connect(url: &str, exchange: &str) -> Result<Self, lapin::Error> {
+        use lapin::options::{ConfirmSelectOptions, ExchangeDeclareOptions};
+
+        let connection = lapin::Connection::connect(url, lapin::ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        channel
+            .exchange_declare(
+                exchange,
+                lapin::ExchangeKind::Topic,
+                ExchangeDeclareOptions { durable: true, ..Default::default() },
+                Default::default(),
+            )
+            .await?;
+        Ok(Self { channel, exchange: exchange.to_owned() })
+    }
+}
+
+#[cfg(feature = "rabbitmq")]
+impl EventSink for RabbitMqSink {
+    fn publish<'a>(&'a self, event: &'a OutboxEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let properties = lapin::BasicProperties::default()
+                .with_message_id(event.id.to_string().into())
+                .with_content_type("application/json".into())
+                .with_delivery_mode(2); // persistent
+            let confirm = self
+                .channel
+                .basic_publish(
+                    &self.exchange,
+                    &event.event_type,
+                    Default::default(),
+                    &event.to_json(),
+                    properties,
+                )
+                .await?
+                .await?;
+            if confirm.is_nack() {
+                return Err(format!("broker nacked event {}", event.id).into());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Publishes to a Kafka topic, keyed by product ID so each product's events
+/// stay in one partition, in order.
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    producer: rdkafka::producer::FutureProducer,
+    topic: String,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    pub fn connect(brokers: &str, topic: &str) -> Result<Self, rdkafka::error::KafkaError> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .create()?;
+        Ok(Self { producer, topic: topic.to_owned() })
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl EventSink for KafkaSink {
+    fn publish<'a>(&'a self, event: &'a OutboxEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        use rdkafka::message::{Header, OwnedHeaders};
+
+        Box::pin(async move {
+            let key = event.product_id.to_string();
+            let id = event.id.to_string();
+            let payload = event.to_json();
+            let record = rdkafka::producer::FutureRecord::to(&self.topic)
+                .key(&key)
+                .payload(&payload)
+                .headers(OwnedHeaders::new().insert(Header { key: "event_id", value: Some(&id) }));
+            self.producer
+                .send(record, Duration::from_secs(10))
+                .await
+                .map_err(|(e, _)| Box::new(e) as SinkError)?;
+            Ok(())
+        })
+    }
+}
+
+/// The sink named by `OUTBOX_SINK` (`log`, `rabbitmq` or `kafka`), or `None`
+/// if it is unset — events then stay in the outbox until a relay runs.
+pub async fn sink_from_env() -> Result<Option<Arc<dyn EventSink>>, SinkError> {
+    let Ok(kind) = std::env::var("OUTBOX_SINK") else {
+        return Ok(None);
+    };
+    let sink: Arc<dyn EventSink> = match kind.as_str() {
+        "log" => Arc::new(LogSink),
+        #[cfg(feature = "rabbitmq")]
+        "rabbitmq" => {
+            let url = std::env::var("RABBITMQ_URL").unwrap_or_else(|_| "amqp://localhost:5672/%2f".into());
+            let exchange = std::env::var("OUTBOX_EXCHANGE").unwrap_or_else(|_| "products".into());
+            Arc::new(RabbitMqSink::connect(&url, &exchange).await?)
+        }
+        #[cfg(feature = "kafka")]
+        "kafka" => {
+            let brokers = std::env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".into());
+            let topic = std::env::var("OUTBOX_TOPIC").unwrap_or_else(|_| "product-events".into());
+            Arc::new(KafkaSink::connect(&brokers, &topic)?)
+        }
+        other => return Err(format!("unknown or not compiled-in OUTBOX_SINK {other:?}").into()),
+    };
+    Ok(Some(sink))
+}
+
+// ---------------------------------------------------------------------------
+// Relay
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// Events published per round.
+    pub batch_size: i64,
+    /// Pause after a round that found fewer than `batch_size` events.
+    pub poll_interval: Duration,
+    /// Pause after a failed round.
+    pub retry_delay: Duration,
+    /// Sent events older than this are deleted.
+    pub retention: Duration,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+            retry_delay: Duration::from_secs(5),
+            retention: Duration::from_secs(7 * 24 * 3600), // 7 days
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum RelayError {
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+
+    #[error("sink rejected event {id}: {source}")]
+    Sink { id: i64, source: SinkError },
+}
+
+/// Start the background relay. Runs for the lifetime of the process; safe
+/// to run on every replica.
+pub fn spawn_relay(db: DbPool, sink: Arc<dyn EventSink>, cfg: RelayConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_prune: Option<Instant> = None;
+        loop {
+            match relay_round(&db, sink.as_ref(), cfg.batch_size).await {
+                // A full batch: there may be more waiting.
+                Ok(sent) if sent as i64 >= cfg.batch_size => continue,
+                Ok(_) => {
+                    if last_prune.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL) {
+                        last_prune = Some(Instant::now());
+                        if let Err(e) = prune(&db, cfg.retention).await {
+                            tracing::warn!(error = %e, "outbox prune failed");
+                        }
+                    }
+                    tokio::time::sleep(cfg.poll_interval).await;
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "outbox relay round failed");
+                    tokio::time::sleep(cfg.retry_delay).await;
+                }
+            }
+        }
+    })
+}
+
+/// Publish up to `batch_size` unsent events in order; returns how many were
+/// sent. Does nothing if another replica's relay holds the lock.
+async fn relay_round(db: &DbPool, sink: &dyn EventSink, batch_size: i64) -> Result<usize, RelayError> {
+    let mut conn = db.acquire().await?;
+
+    let leader: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(RELAY_LOCK_KEY)
+        .fetch_one(&mut *conn)
+        .await?;
+    if !leader {
+        return Ok(0);
+    }
+
+    let result = publish_batch(&mut conn, sink, batch_size).await;
+
+    // A session lock outlives the round: release it before the connection
+    // goes back to the pool, or close the connection if that fails.
+    if let Err(e) = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(RELAY_LOCK_KEY)
+        .execute(&mut *conn)
+        .await
+    {
+        tracing::warn!(error = %e, "failed to release outbox relay lock — closing connection");
+        drop(conn.detach());
+    }
+    result
+}
+
+/// The body of a round, run while holding the relay lock. Each statement
+/// commits on its own; nothing is held open across `publish`.
+async fn publish_batch(conn: &mut PgConnection, sink: &dyn EventSink, batch_size: i64) -> Result<usize, RelayError> {
+    let events = sqlx::query_as::<_, OutboxEvent>(
+        "SELECT id, product_id, event_type, payload, created_at
+           FROM product_outbox
+          WHERE sent_at IS NULL
+          ORDER BY id
+          LIMIT $1",
+    )
+    .bind(batch_size)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut sent = Vec::with_capacity(events.len());
+    let mut failure = None;
+    for event in &events {
+        match sink.publish(event).await {
+            Ok(()) => sent.push(event.id),
+            Err(e) => {
+                // Stop here so nothing overtakes the failed event.
+                failure = Some((event.id, e));
+                break;
+            }
+        }
+    }
+
+    sqlx::query("UPDATE product_outbox SET sent_at = NOW(), attempts = attempts + 1 WHERE id = ANY($1)")
+        .bind(&sent)
+        .execute(&mut *conn)
+        .await?;
+    if let Some((id, e)) = &failure {
+        sqlx::query("UPDATE product_outbox SET attempts = attempts + 1, last_error = $2 WHERE id = $1")
+            .bind(id)
+            .bind(e.to_string())
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    match failure {
+        Some((id, source)) => Err(RelayError::Sink { id, source }),
+        None => Ok(sent.len()),
+    }
+}
+
+/// Delete events sent more than `retention` ago.
+async fn prune(db: &DbPool, retention: Duration) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM product_outbox WHERE sent_at < NOW() - make_interval(secs => $1)")
+        .bind(retention.as_secs_f64())
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn event_types() {
+        assert_eq!(EventKind::Created.event_type(), "product.created");
+        assert_eq!(EventKind::Updated.event_type(), "product.updated");
+        assert_eq!(EventKind::Deleted.event_type(), "product.deleted");
+    }
+
+    #[test]
+    fn message_body_shape() {
+        let product_id = Uuid::new_v4();
+        let created_at = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let event = OutboxEvent {
+            id: 42,
+            product_id,
+            event_type: "product.updated".into(),
+            payload: json!({ "id": product_id, "stock": 3 }),
+            created_at,
+        };
+
+        let body: serde_json::Value = serde_json::from_slice(&event.to_json()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "id": 42,
+                "product_id": product_id,
+                "event_type": "product.updated",
+                "payload": { "id": product_id, "stock": 3 },
+                "created_at": "2024-01-02T03:04:05Z",
+            })
+        );
+    }
+
+    // One test, so nothing else reads OUTBOX_SINK while it changes.
+    #[tokio::test]
+    async fn sink_from_env_choices() {
+        std::env::remove_var("OUTBOX_SINK");
+        assert!(sink_from_env().await.unwrap().is_none());
+
+        std::env::set_var("OUTBOX_SINK", "log");
+        assert!(sink_from_env().await.unwrap().is_some());
+
+        let mut rejected = vec!["carrier-pigeon", ""];
+        if cfg!(not(feature = "rabbitmq")) {
+            rejected.push("rabbitmq");
+        }
+        if cfg!(not(feature = "kafka")) {
+            rejected.push("kafka");
+        }
+        for kind in rejected {
+            std::env::set_var("OUTBOX_SINK", kind);
+            let Err(e) = sink_from_env().await else {
+                panic!("OUTBOX_SINK={kind:?} must be rejected");
+            };
+            assert!(e.to_string().contains("OUTBOX_SINK"), "{e}");
+        }
+        std::env::remove_var("OUTBOX_SINK");
+    }
+}
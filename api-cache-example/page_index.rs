@@ -0,0 +1,181 @@
+/// page_index.rs — Which cached category pages hold which products
+///
+/// Design guarantees:
+///
+/// 1. PRECISE INVALIDATION
+///    - Every page cached in `by_category` is recorded against each product
+///      it contains and against its category, so a write can drop just the
+///      pages it affects instead of every cached page.
+///
+/// 2. BOUNDED BY THE CACHE
+///    - The cache's eviction listener removes a page from the index when it
+///      leaves `by_category` for any reason (TTL, TTI, capacity, explicit), so
+///      the index never outgrows the cache it describes.
+///
+/// 3. NO LOST ENTRIES ON RELOAD
+///    - Entries remember the identity of each `Arc` they were recorded for,
+///      and a key stays indexed while any of them is live. An eviction notice
+///      that arrives after the same key was reloaded only removes the old
+///      value's entries, never the new one's.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use uuid::Uuid;
+
+use crate::{listing::ListingKey, model::Product};
+
+/// Identity of a cached page value: the address of its `Arc` allocation.
+type PageId = usize;
+
+#[derive(Default)]
+pub struct PageIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Product ID → the pages it currently appears on.
+    pages_by_product: HashMap<Uuid, HashMap<ListingKey, HashSet<PageId>>>,
+    /// Category → its cached pages, of every query (including empty pages).
+    pages_by_category: HashMap<String, HashMap<ListingKey, HashSet<PageId>>>,
+}
+
+impl PageIndex {
+    /// Record a page about to be stored in the cache under `key`.
+    pub fn record(&self, key: &ListingKey, page: &Arc<Vec<Product>>) {
+        let page_id = Arc::as_ptr(page) as PageId;
+        let mut inner = self.inner.lock().unwrap();
+
+        inner
+            .pages_by_category
+            .entry(key.category.clone())
+            .or_default()
+            .entry(key.clone())
+            .or_default()
+            .insert(page_id);
+        for product in page.iter() {
+            inner
+                .pages_by_product
+                .entry(product.id)
+                .or_default()
+                .entry(key.clone())
+                .or_default()
+                .insert(page_id);
+        }
+    }
+
+    /// Drop a page value that has left the cache (or never made it in).
+    /// Entries recorded for other values of the same key are kept.
+    pub fn forget(&self, key: &ListingKey, page: &Arc<Vec<Product>>) {
+        let page_id = Arc::as_ptr(page) as PageId;
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(pages) = inner.pages_by_category.get_mut(&key.category) {
+            remove_id(pages, key, page_id);
+            if pages.is_empty() {
+                inner.pages_by_category.remove(&key.category);
+            }
+        }
+        for product in page.iter() {
+            if let Some(pages) = inner.pages_by_product.get_mut(&product.id) {
+                remove_id(pages, key, page_id);
+                if pages.is_empty() {
+                    inner.pages_by_product.remove(&product.id);
+                }
+            }
+        }
+    }
+
+    /// Cached pages that contain product `id`.
+    pub fn pages_containing(&self, id: Uuid) -> Vec<ListingKey> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .pages_by_product
+            .get(&id)
+            .map(|pages| pages.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Every cached page of `category`.
+    pub fn pages_in_category(&self, category: &str) -> Vec<ListingKey> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .pages_by_category
+            .get(category)
+            .map(|pages| pages.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Remove one value identity from `key`, and `key` itself once none are left.
+fn remove_id<K: Eq + std::hash::Hash>(pages: &mut HashMap<K, HashSet<PageId>>, key: &K, page_id: PageId) {
+    if let Some(ids) = pages.get_mut(key) {
+        ids.remove(&page_id);
+        if ids.is_empty() {
+            pages.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::listing::Sort;
+
+    fn product(category: &str) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "widget".into(),
+            category: category.to_owned(),
+            price_cents: 100,
+            stock: 1,
+            updated_at: Utc::now(),
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn forgetting_a_reloaded_key_keeps_the_new_value() {
+        let index = PageIndex::default();
+        let key = ListingKey::first_page("tools");
+        let p = product("tools");
+        let old = Arc::new(vec![p.clone()]);
+        let new = Arc::new(vec![p.clone()]);
+
+        index.record(&key, &old);
+        index.record(&key, &new);
+        // The eviction notice for the old value arrives after the reload.
+        index.forget(&key, &old);
+
+        assert_eq!(index.pages_containing(p.id), vec![key.clone()]);
+        assert_eq!(index.pages_in_category("tools"), vec![key.clone()]);
+
+        index.forget(&key, &new);
+        assert!(index.pages_containing(p.id).is_empty());
+        assert!(index.pages_in_category("tools").is_empty());
+    }
+
+    #[test]
+    fn pages_are_indexed_by_product_and_category() {
+        let index = PageIndex::default();
+        let (a, b) = (product("tools"), product("tools"));
+        let by_name = ListingKey::first_page("tools");
+        let by_price = ListingKey { sort: Sort::PriceAsc, ..ListingKey::first_page("tools") };
+        let empty = ListingKey { min_stock: Some(1_000), ..ListingKey::first_page("tools") };
+
+        index.record(&by_name, &Arc::new(vec![a.clone(), b.clone()]));
+        index.record(&by_price, &Arc::new(vec![b.clone()]));
+        index.record(&empty, &Arc::new(Vec::new()));
+
+        assert_eq!(index.pages_containing(a.id), vec![by_name.clone()]);
+        let mut holding_b = index.pages_containing(b.id);
+        holding_b.sort_by_key(|key| key.sort.column());
+        assert_eq!(holding_b, vec![by_name, by_price]);
+        assert_eq!(index.pages_in_category("tools").len(), 3, "empty pages count too");
+        assert!(index.pages_in_category("toys").is_empty());
+    }
+}
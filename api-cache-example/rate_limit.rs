@@ -0,0 +1,445 @@
+/// rate_limit.rs — Per-client token-bucket rate limiting
+///
+/// Design guarantees:
+///
+/// 1. ONE BUCKET PER CLIENT
+///    - A request carrying a configured API key (`X-Api-Key`) is charged to
+///      that key's bucket; any other request is charged to its client IP.
+///      Unknown keys are ignored rather than trusted, so inventing keys does
+///      not buy a client fresh buckets.
+///
+/// 2. TOKEN BUCKET
+///    - Each bucket holds up to `burst` tokens and refills at `per_second`.
+///      A request takes one token; with none left it is rejected with 429 and
+///      a `Retry-After` of the time until the next token arrives.
+///
+/// 3. BOUNDED MEMORY
+///    - Local buckets live in a `moka` cache with a capacity cap and an idle
+///      expiry equal to the time a bucket takes to refill completely. An idle
+///      bucket that expires would have been full again anyway, so the expiry
+///      never hands a client extra tokens.
+///
+/// 4. SHARED ACROSS REPLICAS (optional)
+///    - With Redis attached, buckets are Redis hashes updated by one Lua
+///      script, atomically and on Redis's clock, so the limit holds across
+///      all replicas together. If Redis errors, the request is charged to
+///      the local bucket instead: limits degrade to per-replica, they never
+///      switch off.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use moka::future::Cache;
+use redis::aio::ConnectionManager;
+
+use crate::error::AppError;
+
+/// Header a client presents its API key in.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Take one token from the bucket at KEYS[1] (a hash of `tokens` and `ts`).
+/// ARGV: capacity, refill rate in tokens per millisecond.
+/// Returns 0 if a token was taken, else the milliseconds until one is free.
+const TAKE_TOKEN_SCRIPT: &str = r#"
+    local capacity = tonumber(ARGV[1])
+    local rate = tonumber(ARGV[2])
+    local time = redis.call('TIME')
+    local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+    local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+    local tokens = tonumber(state[1]) or capacity
+    local ts = tonumber(state[2]) or now
+    tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+
+    local wait = 0
+    if tokens >= 1 then
+        tokens = tokens - 1
+    else
+        wait = math.ceil((1 - tokens) / rate)
+    end
+
+    redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', tostring(now))
+    redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate))
+    return wait
+"#;
+
+// ---------------------------------------------------------------------------
+// Configuration
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Bucket size: the largest burst a client may send at once.
+    pub burst: u32,
+    /// Sustained requests per second per client.
+    pub per_second: f64,
+    /// Known API keys, by key, with the name their bucket is kept under
+    /// (so the key itself never reaches logs or Redis).
+    pub api_keys: HashMap<String, String>,
+    /// Take the client IP from the last `X-Forwarded-For` entry. Only enable
+    /// behind a proxy that sets it; otherwise clients can pick their own IP.
+    pub trust_forwarded_for: bool,
+    /// Most local buckets kept at once.
+    pub max_clients: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 40,
+            per_second: 20.0,
+            api_keys: HashMap::new(),
+            trust_forwarded_for: false,
+            max_clients: 100_000,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Read `RATE_LIMIT_*` settings over the defaults. `None` if
+    /// `RATE_LIMIT_PER_SEC=0`, i.e. rate limiting is switched off.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let defaults = Self::default();
+        let cfg = Self {
+            burst: env_or("RATE_LIMIT_BURST", defaults.burst)?,
+            per_second: env_or("RATE_LIMIT_PER_SEC", defaults.per_second)?,
+            api_keys: match std::env::var("RATE_LIMIT_API_KEYS") {
+                Ok(list) => parse_api_keys(&list)?,
+                Err(_) => defaults.api_keys,
+            },
+            trust_forwarded_for: env_or("RATE_LIMIT_TRUST_FORWARDED_FOR", defaults.trust_forwarded_for)?,
+            max_clients: env_or("RATE_LIMIT_MAX_CLIENTS", defaults.max_clients)?,
+        };
+        if cfg.per_second == 0.0 {
+            return Ok(None);
+        }
+        if !(cfg.per_second > 0.0 && cfg.per_second.is_finite()) || cfg.burst == 0 {
+            return Err("RATE_LIMIT_PER_SEC and RATE_LIMIT_BURST must be positive".into());
+        }
+        Ok(Some(cfg))
+    }
+
+    /// Time an empty bucket takes to fill up again.
+    fn refill_time(&self) -> Duration {
+        Duration::from_secs_f64(f64::from(self.burst) / self.per_second)
+    }
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map_err(|_| format!("{name}: invalid value {value:?}")),
+        Err(_) => Ok(default),
+    }
+}
+
+/// `name:key,name:key` → key → name.
+fn parse_api_keys(list: &str) -> Result<HashMap<String, String>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((name, key)) if !name.is_empty() && !key.is_empty() => Ok((key.to_owned(), name.to_owned())),
+            _ => Err("RATE_LIMIT_API_KEYS: expected name:key entries".to_owned()),
+        })
+        .collect()
+}
+
+/// Connection for `RateLimiter::with_redis`; reconnects on its own once made.
+pub async fn connect_redis(redis_url: &str) -> redis::RedisResult<ConnectionManager> {
+    let client = redis::Client::open(redis_url)?;
+    ConnectionManager::new(client).await
+}
+
+// ---------------------------------------------------------------------------
+// Local bucket
+// ---------------------------------------------------------------------------
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(capacity: f64) -> Self {
+        Self { tokens: capacity, updated: Instant::now() }
+    }
+
+    /// Take one token, or return how long until one is available.
+    fn take(&mut self, capacity: f64, per_second: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Limiter
+// ---------------------------------------------------------------------------
+
+pub struct RateLimiter {
+    cfg: RateLimitConfig,
+    local: Cache<String, Arc<Mutex<Bucket>>>,
+    /// Optional shared buckets; `None` keeps every bucket in this process.
+    redis: Option<ConnectionManager>,
+    /// Namespace for bucket keys in Redis.
+    prefix: String,
+}
+
+impl RateLimiter {
+    pub fn new(cfg: RateLimitConfig) -> Self {
+        let local = Cache::builder()
+            .max_capacity(cfg.max_clients)
+            .time_to_idle(cfg.refill_time())
+            .build();
+        Self { cfg, local, redis: None, prefix: String::new() }
+    }
+
+    /// Keep buckets in Redis so every replica draws on the same ones.
+    pub fn with_redis(mut self, conn: ConnectionManager, prefix: &str) -> Self {
+        self.redis = Some(conn);
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Charge one request to `client`'s bucket.
+    async fn check(&self, client: &str) -> Result<(), AppError> {
+        if let Some(conn) = &self.redis {
+            match self.take_shared(conn.clone(), client).await {
+                Ok(None) => return Ok(()),
+                Ok(Some(retry_after)) => return Err(AppError::RateLimited { retry_after }),
+                Err(e) => tracing::warn!(error = %e, "shared rate limit unavailable — using local bucket"),
+            }
+        }
+        self.take_local(client).await.map_err(|retry_after| AppError::RateLimited { retry_after })
+    }
+
+    async fn take_local(&self, client: &str) -> Result<(), Duration> {
+        let capacity = f64::from(self.cfg.burst);
+        let bucket = self
+            .local
+            .get_with_by_ref(client, async { Arc::new(Mutex::new(Bucket::full(capacity))) })
+            .await;
+        // Never held across an await.
+        let mut bucket = bucket.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        bucket.take(capacity, self.cfg.per_second)
+    }
+
+    /// `Some(wait)` if the shared bucket is empty.
+    async fn take_shared(&self, mut conn: ConnectionManager, client: &str) -> redis::RedisResult<Option<Duration>> {
+        let key = format!("{}:ratelimit:{client}", self.prefix);
+        let wait_ms: u64 = redis::Script::new(TAKE_TOKEN_SCRIPT)
+            .key(key)
+            .arg(self.cfg.burst)
+            .arg(self.cfg.per_second / 1000.0)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok((wait_ms > 0).then(|| Duration::from_millis(wait_ms)))
+    }
+
+    /// Bucket name for a request: its known API key, else its IP.
+    fn client_id(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> String {
+        let key = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok());
+        if let Some(name) = key.and_then(|key| self.cfg.api_keys.get(key)) {
+            return format!("key:{name}");
+        }
+
+        let forwarded = self
+            .cfg
+            .trust_forwarded_for
+            .then(|| forwarded_for(headers))
+            .flatten();
+        match forwarded.or(peer) {
+            Some(ip) => format!("ip:{ip}"),
+            // No socket address (e.g. served without connect info): one
+            // shared bucket, rather than no limit at all.
+            None => "ip:unknown".to_owned(),
+        }
+    }
+}
+
+/// The address the nearest proxy saw: the last `X-Forwarded-For` entry.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .last()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+// ---------------------------------------------------------------------------
+// Middleware
+// ---------------------------------------------------------------------------
+
+/// Reject the request with 429 if its client's bucket is empty.
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = limiter.client_id(request.headers(), peer);
+
+    if let Err(e) = limiter.check(&client).await {
+        tracing::debug!(client, "rate limited");
+        return Err(e);
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn limiter(trust_forwarded_for: bool) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            api_keys: parse_api_keys("mobile:k-123").unwrap(),
+            trust_forwarded_for,
+            ..RateLimitConfig::default()
+        })
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn peer() -> Option<IpAddr> {
+        Some("10.0.0.1".parse().unwrap())
+    }
+
+    // --- Bucket ------------------------------------------------------------
+
+    #[test]
+    fn bucket_allows_a_burst_then_rejects() {
+        let mut bucket = Bucket::full(3.0);
+        for _ in 0..3 {
+            assert!(bucket.take(3.0, 1.0).is_ok());
+        }
+        assert!(bucket.take(3.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn bucket_retry_after_is_time_to_next_token() {
+        let mut bucket = Bucket::full(1.0);
+        bucket.take(1.0, 2.0).unwrap();
+
+        let wait = bucket.take(1.0, 2.0).unwrap_err();
+        // Empty at 2 tokens/s: the next one is due in just under 500 ms.
+        assert!(wait <= Duration::from_millis(500), "{wait:?}");
+        assert!(wait > Duration::from_millis(450), "{wait:?}");
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut bucket = Bucket::full(2.0);
+        bucket.take(2.0, 10.0).unwrap();
+        bucket.take(2.0, 10.0).unwrap();
+        assert!(bucket.take(2.0, 10.0).is_err());
+
+        // 150 ms at 10 tokens/s: one token back, not two.
+        bucket.updated = bucket.updated.checked_sub(Duration::from_millis(150)).unwrap();
+        assert!(bucket.take(2.0, 10.0).is_ok());
+        assert!(bucket.take(2.0, 10.0).is_err());
+    }
+
+    #[test]
+    fn bucket_never_refills_past_capacity() {
+        let mut bucket = Bucket::full(2.0);
+        bucket.updated = bucket.updated.checked_sub(Duration::from_secs(60)).unwrap();
+        for _ in 0..2 {
+            assert!(bucket.take(2.0, 10.0).is_ok());
+        }
+        assert!(bucket.take(2.0, 10.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn limiter_rejects_with_retry_after() {
+        let limiter = RateLimiter::new(RateLimitConfig { burst: 2, per_second: 1.0, ..RateLimitConfig::default() });
+        assert!(limiter.check("ip:a").await.is_ok());
+        assert!(limiter.check("ip:a").await.is_ok());
+        let Err(AppError::RateLimited { retry_after }) = limiter.check("ip:a").await else {
+            panic!("third request must be limited");
+        };
+        assert!(retry_after <= Duration::from_secs(1));
+        // Another client has its own bucket.
+        assert!(limiter.check("ip:b").await.is_ok());
+    }
+
+    // --- Configuration -----------------------------------------------------
+
+    #[test]
+    fn api_keys_map_key_to_name() {
+        let keys = parse_api_keys(" mobile:k-123 , web:k-456,").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys["k-123"], "mobile");
+        assert_eq!(keys["k-456"], "web");
+        assert!(parse_api_keys("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn api_keys_reject_malformed_entries() {
+        for list in ["mobile", "mobile:", ":k-123", "web:k-456,oops"] {
+            assert!(parse_api_keys(list).is_err(), "{list:?}");
+        }
+    }
+
+    // --- Client identity ---------------------------------------------------
+
+    #[test]
+    fn forwarded_for_takes_the_last_entry() {
+        let request = headers(&[("x-forwarded-for", "1.1.1.1, 2.2.2.2"), ("x-forwarded-for", "3.3.3.3 ")]);
+        assert_eq!(forwarded_for(&request), Some("3.3.3.3".parse().unwrap()));
+        assert_eq!(forwarded_for(&headers(&[("x-forwarded-for", "1.1.1.1, junk")])), None);
+        assert_eq!(forwarded_for(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn forwarded_for_only_when_trusted() {
+        let request = headers(&[("x-forwarded-for", "203.0.113.9")]);
+        assert_eq!(limiter(false).client_id(&request, peer()), "ip:10.0.0.1");
+        assert_eq!(limiter(true).client_id(&request, peer()), "ip:203.0.113.9");
+    }
+
+    #[test]
+    fn known_api_key_gets_its_own_bucket() {
+        let request = headers(&[("x-api-key", "k-123")]);
+        assert_eq!(limiter(false).client_id(&request, peer()), "key:mobile");
+    }
+
+    #[test]
+    fn unknown_api_key_falls_back_to_ip() {
+        let request = headers(&[("x-api-key", "made-up")]);
+        assert_eq!(limiter(false).client_id(&request, peer()), "ip:10.0.0.1");
+        assert_eq!(limiter(false).client_id(&request, None), "ip:unknown");
+    }
+}
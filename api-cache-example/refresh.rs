@@ -0,0 +1,184 @@
+/// refresh.rs — Building blocks for stale-while-revalidate / refresh-ahead
+///
+/// Design guarantees:
+///
+/// 1. NO LATENCY CLIFF AT EXPIRY
+///    - Every cached value carries the time it was loaded. Past its soft TTL
+///      it is still served, and a background task reloads it; readers only
+///      pay DB latency once the hard TTL (moka's `time_to_live`) has passed.
+///
+/// 2. ONE REFRESH PER KEY
+///    - `RefreshSet` admits a single in-flight refresh per key, however many
+///      readers see the entry as stale at once.
+///
+/// 3. REFRESH NEVER RESURRECTS
+///    - A refresh only replaces the exact value it set out to refresh (same
+///      `Arc`). If the key was invalidated or reloaded in the meantime, the
+///      refreshed value is dropped — it could be older than what a write has
+///      since committed. Refreshes never write L2 for the same reason: there
+///      is nothing there to compare against.
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use moka::ops::compute::Op;
+
+// ---------------------------------------------------------------------------
+// Timestamped values
+// ---------------------------------------------------------------------------
+
+/// A cached value plus when it was loaded.
+#[derive(Debug)]
+pub struct Timed<V> {
+    pub value: Arc<V>,
+    pub loaded_at: Instant,
+}
+
+// Manual impl: only the `Arc` is cloned, so `V: Clone` is not required.
+impl<V> Clone for Timed<V> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            loaded_at: self.loaded_at,
+        }
+    }
+}
+
+impl<V> Timed<V> {
+    pub fn new(value: Arc<V>) -> Self {
+        Self {
+            value,
+            loaded_at: Instant::now(),
+        }
+    }
+
+    /// True once the entry is older than `soft_ttl` (never, if `None`).
+    pub fn is_stale(&self, soft_ttl: Option<Duration>) -> bool {
+        soft_ttl.is_some_and(|soft| self.loaded_at.elapsed() >= soft)
+    }
+}
+
+/// `and_compute_with` step: install `fresh` only if the cache still holds
+/// `stale` itself.
+pub fn replace_if_unchanged<K, V>(
+    current: Option<moka::Entry<K, Timed<V>>>,
+    stale: &Arc<V>,
+    fresh: Timed<V>,
+) -> std::future::Ready<Op<Timed<V>>> {
+    let op = match current {
+        Some(entry) if Arc::ptr_eq(&entry.value().value, stale) => Op::Put(fresh),
+        _ => Op::Nop,
+    };
+    std::future::ready(op)
+}
+
+// ---------------------------------------------------------------------------
+// In-flight refresh tracking
+// ---------------------------------------------------------------------------
+
+/// Keys with a background refresh currently running.
+pub struct RefreshSet<K> {
+    in_flight: Mutex<HashSet<K>>,
+}
+
+impl<K> Default for RefreshSet<K> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> RefreshSet<K> {
+    /// Claim `key` for refreshing. Returns `None` if a refresh is already
+    /// running; otherwise a guard that releases the claim when dropped (even
+    /// if the refresh task panics).
+    pub fn try_start(self: &Arc<Self>, key: &K) -> Option<RefreshGuard<K>> {
+        if !self.in_flight.lock().unwrap().insert(key.clone()) {
+            return None;
+        }
+        Some(RefreshGuard {
+            set: self.clone(),
+            key: key.clone(),
+        })
+    }
+}
+
+pub struct RefreshGuard<K: Eq + Hash> {
+    set: Arc<RefreshSet<K>>,
+    key: K,
+}
+
+impl<K: Eq + Hash> Drop for RefreshGuard<K> {
+    fn drop(&mut self) {
+        self.set.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moka::{future::Cache, ops::compute::CompResult};
+
+    use super::*;
+
+    /// Run the install step of a refresh that set out to replace `stale`.
+    async fn install(cache: &Cache<u32, Timed<String>>, stale: &Arc<String>, fresh: &str) -> bool {
+        let fresh = Timed::new(Arc::new(fresh.to_owned()));
+        let result = cache
+            .entry(1)
+            .and_compute_with(|current| replace_if_unchanged(current, stale, fresh))
+            .await;
+        matches!(result, CompResult::ReplacedWith(_))
+    }
+
+    #[tokio::test]
+    async fn refresh_replaces_the_value_it_refreshed() {
+        let cache = Cache::new(10);
+        let stale = Arc::new("v1".to_owned());
+        cache.insert(1, Timed::new(stale.clone())).await;
+
+        assert!(install(&cache, &stale, "v2").await);
+        assert_eq!(*cache.get(&1).await.unwrap().value, "v2");
+    }
+
+    #[tokio::test]
+    async fn refresh_racing_an_invalidate_is_dropped() {
+        let cache = Cache::new(10);
+        let stale = Arc::new("v1".to_owned());
+        cache.insert(1, Timed::new(stale.clone())).await;
+
+        // A write commits and invalidates while the refresh's DB read (which
+        // saw the pre-write row) is in flight.
+        cache.invalidate(&1).await;
+
+        assert!(!install(&cache, &stale, "v1-reread").await);
+        assert!(cache.get(&1).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn refresh_racing_a_reload_keeps_the_reload() {
+        let cache = Cache::new(10);
+        let stale = Arc::new("v1".to_owned());
+        cache.insert(1, Timed::new(stale.clone())).await;
+
+        // Invalidated and reloaded with the post-write value meanwhile.
+        cache.invalidate(&1).await;
+        cache.insert(1, Timed::new(Arc::new("v2".to_owned()))).await;
+
+        assert!(!install(&cache, &stale, "v1-reread").await);
+        assert_eq!(*cache.get(&1).await.unwrap().value, "v2");
+    }
+
+    #[test]
+    fn one_refresh_per_key() {
+        let set = Arc::new(RefreshSet::default());
+        let guard = set.try_start(&1).expect("first refresh starts");
+        assert!(set.try_start(&1).is_none());
+        assert!(set.try_start(&2).is_some());
+        drop(guard);
+        assert!(set.try_start(&1).is_some());
+    }
+}
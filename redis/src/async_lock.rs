@@ -0,0 +1,253 @@
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisResult};
+use uuid::Uuid;
+
+use crate::{Attempt, ACQUIRE_SCRIPT, RELEASE_SCRIPT};
+
+/// Identifies who holds an [`AsyncRedissonLock`]
+///
+/// Tokio tasks migrate between worker threads, so ownership cannot be tied to the OS
+/// thread like `RedissonLock` does. Instead each logical holder gets a token, which can be
+/// moved between tasks along with the critical section it protects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LockOwner(String);
+
+impl LockOwner {
+    /// Hash field stored in the lock: "{uuid}:{owner_id}"
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Async counterpart of `RedissonLock` built on a `ConnectionManager`
+///
+/// Uses the same key (`lock:{name}`), hash layout and Lua scripts, so sync and async
+/// clients can contend for the same lock.
+#[derive(Clone)]
+pub struct AsyncRedissonLock {
+    conn: ConnectionManager,
+    lock_name: String,
+    uuid: String,
+    lease_time_ms: u64,
+}
+
+impl AsyncRedissonLock {
+    pub async fn new(redis_url: &str, lock_name: &str, lease_time_ms: u64) -> RedisResult<Self> {
+        let client = Client::open(redis_url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self::with_connection_manager(conn, lock_name, lease_time_ms))
+    }
+
+    /// Build a lock on an existing connection manager, so many locks can share one
+    pub fn with_connection_manager(conn: ConnectionManager, lock_name: &str, lease_time_ms: u64) -> Self {
+        AsyncRedissonLock {
+            conn,
+            lock_name: lock_name.to_string(),
+            uuid: Uuid::new_v4().to_string(),
+            lease_time_ms,
+        }
+    }
+
+    /// Create a new owner token for this client instance
+    pub fn new_owner(&self) -> LockOwner {
+        LockOwner(format!("{}:{}", self.uuid, Uuid::new_v4()))
+    }
+
+    /// Check if `owner` holds the lock
+    pub async fn is_held_by(&self, owner: &LockOwner) -> RedisResult<bool> {
+        Ok(self.get_hold_count(owner).await? > 0)
+    }
+
+    /// Number of times `owner` has acquired the lock without releasing it
+    pub async fn get_hold_count(&self, owner: &LockOwner) -> RedisResult<u32> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let count: Option<u32> = conn.hget(&lock_key, owner.id()).await?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Acquire the lock for `owner` without waiting
+    ///
+    /// Reentrant: if `owner` already holds the lock, its hold count is incremented and
+    /// the lease is reset.
+    pub async fn try_lock(&self, owner: &LockOwner) -> RedisResult<bool> {
+        Ok(self.try_lock_fenced(owner).await?.is_some())
+    }
+
+    /// Like `try_lock`, returning the fencing token of the hold if acquired
+    /// See `RedissonLock::fencing_token` for how tokens are drawn and meant to be used.
+    pub async fn try_lock_fenced(&self, owner: &LockOwner) -> RedisResult<Option<u64>> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let attempt: Attempt = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(format!("lock_fence:{}", self.lock_name))
+            .arg(self.lease_time_ms)
+            .arg(owner.id())
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(match attempt {
+            Attempt::Acquired(token) => Some(token),
+            Attempt::Held(_) => None,
+        })
+    }
+
+    /// Fencing token of `owner`'s hold, or None if it does not hold the lock
+    pub async fn fencing_token(&self, owner: &LockOwner) -> RedisResult<Option<u64>> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let (count, token): (Option<u32>, Option<u64>) = redis::pipe()
+            .hget(&lock_key, owner.id())
+            .hget(&lock_key, "fence")
+            .query_async(&mut conn)
+            .await?;
+        Ok(token.filter(|_| count.unwrap_or(0) > 0))
+    }
+
+    /// Release one hold of the lock by `owner`
+    ///
+    /// Deletes the key once the hold count reaches zero. Returns false if `owner` does
+    /// not hold the lock.
+    pub async fn unlock(&self, owner: &LockOwner) -> RedisResult<bool> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let result: Option<i32> = redis::Script::new(RELEASE_SCRIPT)
+            .key(&lock_key)
+            .key(format!("lock_channel:{}", self.lock_name))
+            .arg(self.lease_time_ms)
+            .arg(owner.id())
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(result.is_some())
+    }
+
+    /// Check if anyone holds the lock
+    pub async fn is_locked(&self) -> RedisResult<bool> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let exists: bool = conn.exists(&lock_key).await?;
+        Ok(exists)
+    }
+
+    /// Get the remaining time to live for the lock in milliseconds
+    pub async fn get_remaining_time_to_live(&self) -> RedisResult<i64> {
+        let mut conn = self.conn.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let ttl: i64 = conn.pttl(&lock_key).await?;
+        Ok(ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_async_lock_owner_semantics() {
+        let lock = AsyncRedissonLock::new(REDIS_URL, "ASYNC_OWNER_LOCK", 30000).await.unwrap();
+        let owner = lock.new_owner();
+        let other = lock.new_owner();
+
+        assert!(!lock.is_locked().await.unwrap());
+        assert!(lock.try_lock(&owner).await.unwrap());
+        assert!(lock.is_held_by(&owner).await.unwrap());
+        assert!(lock.get_remaining_time_to_live().await.unwrap() > 0);
+
+        // Another owner on the same client can neither take nor release it
+        assert!(!lock.try_lock(&other).await.unwrap());
+        assert!(!lock.is_held_by(&other).await.unwrap());
+        assert!(!lock.unlock(&other).await.unwrap());
+        assert!(lock.is_locked().await.unwrap());
+
+        // Clean up
+        assert!(lock.unlock(&owner).await.unwrap());
+        assert!(!lock.is_locked().await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_async_lock_reentrant() {
+        let lock = AsyncRedissonLock::new(REDIS_URL, "ASYNC_REENTRANT_LOCK", 30000).await.unwrap();
+        let owner = lock.new_owner();
+
+        assert!(lock.try_lock(&owner).await.unwrap());
+        assert!(lock.try_lock(&owner).await.unwrap());
+        assert_eq!(lock.get_hold_count(&owner).await.unwrap(), 2);
+
+        assert!(lock.unlock(&owner).await.unwrap());
+        assert!(lock.is_locked().await.unwrap());
+        assert!(lock.unlock(&owner).await.unwrap());
+        assert!(!lock.is_locked().await.unwrap());
+        assert!(!lock.unlock(&owner).await.unwrap());
+    }
+
+    /// Ownership follows the token, not the worker thread the task happens to run on
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn test_async_lock_ownership_moves_with_task() {
+        let lock = AsyncRedissonLock::new(REDIS_URL, "ASYNC_TASK_LOCK", 30000).await.unwrap();
+        let owner = lock.new_owner();
+        assert!(lock.try_lock(&owner).await.unwrap());
+
+        let task_lock = lock.clone();
+        let task_owner = owner.clone();
+        let released = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(task_lock.is_held_by(&task_owner).await.unwrap());
+            task_lock.unlock(&task_owner).await.unwrap()
+        })
+        .await
+        .unwrap();
+
+        assert!(released, "Owner token should be able to release from another task");
+        assert!(!lock.is_locked().await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_async_lock_expiration() {
+        let lock = AsyncRedissonLock::new(REDIS_URL, "ASYNC_EXPIRATION_LOCK", 200).await.unwrap();
+        let owner = lock.new_owner();
+        assert!(lock.try_lock(&owner).await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!lock.is_held_by(&owner).await.unwrap(), "Lock should have expired");
+
+        let next = lock.new_owner();
+        assert!(lock.try_lock(&next).await.unwrap());
+        assert!(lock.unlock(&next).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_async_lock_fencing_tokens() {
+        let lock = AsyncRedissonLock::new(REDIS_URL, "ASYNC_FENCING_LOCK", 30000).await.unwrap();
+        let owner = lock.new_owner();
+        let other = lock.new_owner();
+
+        let token = lock.try_lock_fenced(&owner).await.unwrap().expect("lock should be free");
+        assert_eq!(lock.try_lock_fenced(&owner).await.unwrap(), Some(token), "Re-entry keeps the token");
+        assert_eq!(lock.fencing_token(&owner).await.unwrap(), Some(token));
+        assert_eq!(lock.try_lock_fenced(&other).await.unwrap(), None);
+        assert_eq!(lock.fencing_token(&other).await.unwrap(), None);
+
+        assert!(lock.unlock(&owner).await.unwrap());
+        assert!(lock.unlock(&owner).await.unwrap());
+
+        let next = lock.try_lock_fenced(&other).await.unwrap().expect("lock should be free");
+        assert!(next > token);
+        assert!(lock.unlock(&other).await.unwrap());
+    }
+}
@@ -0,0 +1,165 @@
+use redis::{Client, Commands, RedisResult};
+use std::time::Duration;
+
+use crate::wait_for_release;
+
+/// How often waiters re-check the count in case a wakeup was missed
+const LATCH_POLL_MS: i64 = 1000;
+
+/// Set the count, unless a count is already running
+/// KEYS: latch. ARGV: count
+/// Returns 1 if set, 0 if the latch is already counting down
+const LATCH_SET_COUNT_SCRIPT: &str = r#"
+    if redis.call('EXISTS', KEYS[1]) == 1 then
+        return 0
+    end
+    redis.call('SET', KEYS[1], ARGV[1])
+    return 1
+"#;
+
+/// Count down once, deleting the latch and waking the waiters when it reaches zero
+/// KEYS: latch, channel
+/// Returns the remaining count
+const LATCH_COUNT_DOWN_SCRIPT: &str = r#"
+    if redis.call('EXISTS', KEYS[1]) == 0 then
+        return 0
+    end
+    local count = redis.call('DECR', KEYS[1])
+    if count <= 0 then
+        redis.call('DEL', KEYS[1])
+        redis.call('PUBLISH', KEYS[2], 'zero')
+        return 0
+    end
+    return count
+"#;
+
+/// Distributed countdown latch, like Redisson's RCountDownLatch
+///
+/// The count lives in `latch:{name}`; the key is deleted once the count reaches zero,
+/// which also makes the latch reusable with a new `try_set_count`.
+#[derive(Debug, Clone)]
+pub struct RedissonCountDownLatch {
+    client: Client,
+    name: String,
+}
+
+impl RedissonCountDownLatch {
+    pub fn new(redis_url: &str, name: &str) -> RedisResult<Self> {
+        Ok(RedissonCountDownLatch {
+            client: Client::open(redis_url)?,
+            name: name.to_string(),
+        })
+    }
+
+    /// Start counting down from `count`
+    /// Returns false if the latch is already counting down
+    pub fn try_set_count(&self, count: u32) -> RedisResult<bool> {
+        let mut conn = self.client.get_connection()?;
+
+        let set: i32 = redis::Script::new(LATCH_SET_COUNT_SCRIPT)
+            .key(self.latch_name())
+            .arg(count)
+            .invoke(&mut conn)?;
+        Ok(set == 1)
+    }
+
+    /// Decrement the count, releasing every waiter when it reaches zero
+    pub fn count_down(&self) -> RedisResult<u32> {
+        let mut conn = self.client.get_connection()?;
+
+        redis::Script::new(LATCH_COUNT_DOWN_SCRIPT)
+            .key(self.latch_name())
+            .key(self.channel_name())
+            .invoke(&mut conn)
+    }
+
+    pub fn get_count(&self) -> RedisResult<u32> {
+        let mut conn = self.client.get_connection()?;
+        let count: Option<u32> = conn.get(self.latch_name())?;
+        Ok(count.unwrap_or(0))
+    }
+
+    /// Block until the count reaches zero
+    pub fn wait(&self) -> RedisResult<()> {
+        wait_for_release(&self.client, &self.channel_name(), None, || self.check_zero())?;
+        Ok(())
+    }
+
+    /// Block up to `timeout` for the count to reach zero
+    /// Returns false if it is still counting down
+    pub fn wait_for(&self, timeout: Duration) -> RedisResult<bool> {
+        wait_for_release(&self.client, &self.channel_name(), Some(timeout), || self.check_zero())
+    }
+
+    fn latch_name(&self) -> String {
+        format!("latch:{}", self.name)
+    }
+
+    fn channel_name(&self) -> String {
+        format!("latch_channel:{}", self.name)
+    }
+
+    /// None once the count is zero, otherwise how long to wait before checking again
+    fn check_zero(&self) -> RedisResult<Option<i64>> {
+        Ok((self.get_count()? > 0).then_some(LATCH_POLL_MS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    #[test]
+    #[ignore]
+    fn test_latch_releases_waiters_at_zero() {
+        let name = "LATCH_RELEASE";
+        let latch = RedissonCountDownLatch::new(REDIS_URL, name).unwrap();
+        assert!(latch.try_set_count(3).unwrap());
+        assert!(!latch.try_set_count(5).unwrap(), "Count is only set once per round");
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                thread::spawn(move || {
+                    let latch = RedissonCountDownLatch::new(REDIS_URL, name).unwrap();
+                    let started = Instant::now();
+                    latch.wait().unwrap();
+                    started.elapsed()
+                })
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(latch.count_down().unwrap(), 2);
+        assert_eq!(latch.count_down().unwrap(), 1);
+        assert_eq!(latch.get_count().unwrap(), 1);
+        assert_eq!(latch.count_down().unwrap(), 0);
+
+        for waiter in waiters {
+            let waited = waiter.join().unwrap();
+            assert!(waited >= Duration::from_millis(100), "Waiter returned before zero");
+            assert!(waited < Duration::from_millis(900), "Waiter should wake on publish");
+        }
+
+        // Counting down past zero is a no-op, and the latch can be reused
+        assert_eq!(latch.count_down().unwrap(), 0);
+        assert!(latch.try_set_count(1).unwrap());
+        assert_eq!(latch.count_down().unwrap(), 0);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_latch_wait_for_times_out() {
+        let name = "LATCH_TIMEOUT";
+        let latch = RedissonCountDownLatch::new(REDIS_URL, name).unwrap();
+        assert!(latch.try_set_count(1).unwrap());
+
+        assert!(!latch.wait_for(Duration::from_millis(200)).unwrap());
+
+        assert_eq!(latch.count_down().unwrap(), 0);
+        assert!(latch.wait_for(Duration::from_millis(200)).unwrap());
+    }
+}
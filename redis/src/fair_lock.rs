@@ -0,0 +1,378 @@
+use redis::RedisResult;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{Attempt, RedissonLock, RedissonLockGuard};
+
+/// How long a queued waiter may go without checking in before it is dropped from the
+/// queue, same default as Redisson's threadWaitTime
+pub const DEFAULT_THREAD_WAIT_TIME_MS: u64 = 5_000;
+
+/// Purge stale waiters, then take the lock if it is ours (re-entry) or if it is free and we
+/// are first in line. Otherwise optionally join the queue (or refresh our place in it).
+/// KEYS: lock hash, waiter queue (list), waiter deadlines (zset), fencing counter
+/// ARGV: lease ms, holder, thread wait ms, now ms, "1" to queue up
+/// Returns {1, fencing token} on success, or {0, PTTL} of the lock (negative if free but
+/// others are ahead)
+const FAIR_ACQUIRE_SCRIPT: &str = r#"
+    local lock_key = KEYS[1]
+    local queue_key = KEYS[2]
+    local timeout_key = KEYS[3]
+    local fence_key = KEYS[4]
+    local lease_ms = ARGV[1]
+    local holder = ARGV[2]
+    local wait_ms = tonumber(ARGV[3])
+    local now_ms = tonumber(ARGV[4])
+
+    local expired = redis.call('ZRANGEBYSCORE', timeout_key, '-inf', now_ms)
+    for i = 1, #expired do
+        redis.call('LREM', queue_key, 0, expired[i])
+        redis.call('ZREM', timeout_key, expired[i])
+    end
+
+    if redis.call('HEXISTS', lock_key, holder) == 1 then
+        redis.call('HINCRBY', lock_key, holder, 1)
+        redis.call('PEXPIRE', lock_key, lease_ms)
+        return {1, tonumber(redis.call('HGET', lock_key, 'fence') or '0')}
+    end
+
+    if redis.call('EXISTS', lock_key) == 0 then
+        local first = redis.call('LINDEX', queue_key, 0)
+        if first == false or first == holder then
+            if first == holder then
+                redis.call('LPOP', queue_key)
+                redis.call('ZREM', timeout_key, holder)
+            end
+            local token = redis.call('INCR', fence_key)
+            redis.call('HSET', lock_key, 'fence', token, holder, 1)
+            redis.call('PEXPIRE', lock_key, lease_ms)
+            return {1, token}
+        end
+    end
+
+    if ARGV[5] == '1' then
+        if redis.call('ZADD', timeout_key, now_ms + wait_ms, holder) == 1 then
+            redis.call('RPUSH', queue_key, holder)
+        end
+    end
+    return {0, redis.call('PTTL', lock_key)}
+"#;
+
+/// Leave the queue and let the waiters behind us re-check
+/// KEYS: waiter queue, waiter deadlines, lock channel
+/// ARGV: holder
+const FAIR_DEQUEUE_SCRIPT: &str = r#"
+    redis.call('LREM', KEYS[1], 0, ARGV[1])
+    redis.call('ZREM', KEYS[2], ARGV[1])
+    redis.call('PUBLISH', KEYS[3], 'dequeued')
+    return 1
+"#;
+
+/// Fair (FIFO) variant of `RedissonLock`, like Redisson's FairLock
+///
+/// Waiters line up in `lock_queue:{name}` and acquire in arrival order. Each waiter also
+/// has a deadline in `lock_timeout:{name}`, refreshed while it keeps waiting; waiters that
+/// give up leave the queue, and ones that stop checking in (e.g. crashed) are purged once
+/// their deadline passes.
+#[derive(Debug, Clone)]
+pub struct RedissonFairLock {
+    lock: RedissonLock,
+    thread_wait_time_ms: u64,
+}
+
+impl RedissonFairLock {
+    pub fn new(redis_url: &str, lock_name: &str, lease_time_ms: u64) -> RedisResult<Self> {
+        Ok(RedissonFairLock {
+            lock: RedissonLock::new(redis_url, lock_name, lease_time_ms)?,
+            thread_wait_time_ms: DEFAULT_THREAD_WAIT_TIME_MS,
+        })
+    }
+
+    /// Create a fair lock without an explicit lease, kept alive by the watchdog while held
+    pub fn with_watchdog(redis_url: &str, lock_name: &str, watchdog_timeout_ms: u64) -> RedisResult<Self> {
+        Ok(RedissonFairLock {
+            lock: RedissonLock::with_watchdog(redis_url, lock_name, watchdog_timeout_ms)?,
+            thread_wait_time_ms: DEFAULT_THREAD_WAIT_TIME_MS,
+        })
+    }
+
+    /// How long a waiter may stay silent before others may skip it
+    pub fn with_thread_wait_time(mut self, thread_wait_time: Duration) -> Self {
+        self.thread_wait_time_ms = thread_wait_time.as_millis() as u64;
+        self
+    }
+
+    /// Acquire the lock if it is free and nobody is queued ahead of us
+    /// Does not join the queue
+    pub fn try_lock(&self) -> RedisResult<bool> {
+        Ok(matches!(self.try_acquire(self.lock.lease_time_ms, false)?, Attempt::Acquired(_)))
+    }
+
+    /// Acquire the lock, waiting in line as long as it takes
+    pub fn lock(&self) -> RedisResult<()> {
+        self.acquire_waiting(None, self.lock.lease_time_ms)?;
+        Ok(())
+    }
+
+    /// Wait in line up to `wait` for the lock, leaving the queue on timeout
+    /// With `lease` set to None the watchdog keeps the lock alive until unlock
+    pub fn try_lock_for(&self, wait: Duration, lease: Option<Duration>) -> RedisResult<bool> {
+        let lease_ms = lease.map(|l| l.as_millis() as u64);
+        Ok(self.acquire_waiting(Some(wait), lease_ms)?.is_some())
+    }
+
+    /// Acquire the lock, waiting in line, and return a guard that releases it on drop
+    pub fn lock_guarded(&self) -> RedisResult<RedissonLockGuard<'_>> {
+        let token = self
+            .acquire_waiting(None, self.lock.lease_time_ms)?
+            .expect("waiting without a deadline only returns once acquired");
+        Ok(self.lock.guard(token))
+    }
+
+    /// Like `try_lock_for`, returning a guard that releases the lock on drop
+    pub fn try_lock_for_guarded(
+        &self,
+        wait: Duration,
+        lease: Option<Duration>,
+    ) -> RedisResult<Option<RedissonLockGuard<'_>>> {
+        let lease_ms = lease.map(|l| l.as_millis() as u64);
+        Ok(self.acquire_waiting(Some(wait), lease_ms)?.map(|token| self.lock.guard(token)))
+    }
+
+    /// Release one hold of the lock, waking the waiters
+    pub fn unlock(&self) -> RedisResult<bool> {
+        self.lock.unlock()
+    }
+
+    pub fn is_held_by_current_thread(&self) -> RedisResult<bool> {
+        self.lock.is_held_by_current_thread()
+    }
+
+    pub fn get_hold_count(&self) -> RedisResult<u32> {
+        self.lock.get_hold_count()
+    }
+
+    /// Fencing token of the current thread's hold, see `RedissonLock::fencing_token`
+    pub fn fencing_token(&self) -> RedisResult<Option<u64>> {
+        self.lock.fencing_token()
+    }
+
+    pub fn is_locked(&self) -> RedisResult<bool> {
+        self.lock.is_locked()
+    }
+
+    pub fn get_remaining_time_to_live(&self) -> RedisResult<i64> {
+        self.lock.get_remaining_time_to_live()
+    }
+
+    fn queue_name(&self) -> String {
+        format!("lock_queue:{}", self.lock.lock_name)
+    }
+
+    fn timeout_name(&self) -> String {
+        format!("lock_timeout:{}", self.lock.lock_name)
+    }
+
+    /// Single acquisition attempt, optionally (re)joining the queue
+    fn try_acquire(&self, lease_ms: Option<u64>, enqueue: bool) -> RedisResult<Attempt> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let attempt: Attempt = redis::Script::new(FAIR_ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(self.queue_name())
+            .key(self.timeout_name())
+            .key(self.lock.fence_name())
+            .arg(lease_ms.unwrap_or(self.lock.watchdog_timeout_ms))
+            .arg(self.lock.holder_id())
+            .arg(self.thread_wait_time_ms)
+            .arg(now_ms)
+            .arg(if enqueue { "1" } else { "0" })
+            .invoke(&mut conn)?;
+
+        if matches!(attempt, Attempt::Acquired(_)) && lease_ms.is_none() {
+            self.lock.schedule_renewal(self.lock.holder_id());
+        }
+
+        Ok(attempt)
+    }
+
+    /// Queue up and retry until we are first in line and the lock is free
+    ///
+    /// Each retry refreshes our deadline in the timeout set, so we wake at least every
+    /// third of the thread wait time even if nobody publishes. Returns the fencing token
+    /// if acquired.
+    fn acquire_waiting(&self, wait: Option<Duration>, lease_ms: Option<u64>) -> RedisResult<Option<u64>> {
+        let deadline = wait.map(|w| Instant::now() + w);
+        let heartbeat = Duration::from_millis((self.thread_wait_time_ms / 3).max(1));
+
+        let mut conn = self.lock.client.get_connection()?;
+        let mut pubsub = conn.as_pubsub();
+        pubsub.subscribe(self.lock.channel_name())?;
+
+        loop {
+            let ttl = match self.try_acquire(lease_ms, true)? {
+                Attempt::Acquired(token) => return Ok(Some(token)),
+                Attempt::Held(ttl) => ttl,
+            };
+
+            let mut timeout = if ttl > 0 {
+                heartbeat.min(Duration::from_millis(ttl as u64))
+            } else {
+                heartbeat
+            };
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    self.dequeue()?;
+                    return Ok(None);
+                }
+                timeout = timeout.min(remaining);
+            }
+
+            pubsub.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+            match pubsub.get_message() {
+                Ok(_) => {}
+                Err(e) if e.is_timeout() => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Remove the current thread from the queue
+    fn dequeue(&self) -> RedisResult<()> {
+        let mut conn = self.lock.client.get_connection()?;
+
+        let _: i32 = redis::Script::new(FAIR_DEQUEUE_SCRIPT)
+            .key(self.queue_name())
+            .key(self.timeout_name())
+            .key(self.lock.channel_name())
+            .arg(self.lock.holder_id())
+            .invoke(&mut conn)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Commands;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    fn queue_len(lock_name: &str) -> usize {
+        let client = redis::Client::open(REDIS_URL).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        conn.llen(format!("lock_queue:{}", lock_name)).unwrap()
+    }
+
+    /// Waiters get the lock in the order they queued up
+    #[test]
+    #[ignore]
+    fn test_fair_lock_fifo_order() {
+        let lock_name = "FAIR_FIFO_LOCK";
+        let num_waiters = 5;
+
+        let holder = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(holder.try_lock().unwrap());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let handles: Vec<_> = (0..num_waiters)
+            .map(|i| {
+                let order = order.clone();
+                let handle = thread::spawn(move || {
+                    let lock = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+                    lock.lock().unwrap();
+                    order.lock().unwrap().push(i);
+                    thread::sleep(Duration::from_millis(20));
+                    assert!(lock.unlock().unwrap());
+                });
+                // Stagger arrivals so the queue order is well defined
+                while queue_len(lock_name) < i + 1 {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                handle
+            })
+            .collect();
+
+        assert!(holder.unlock().unwrap());
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), (0..num_waiters).collect::<Vec<_>>());
+        assert_eq!(queue_len(lock_name), 0);
+    }
+
+    /// A free lock is not handed to a newcomer while someone is queued ahead of it
+    #[test]
+    #[ignore]
+    fn test_fair_lock_try_lock_respects_queue() {
+        let lock_name = "FAIR_QUEUE_LOCK";
+        let client = redis::Client::open(REDIS_URL).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        // A live waiter (deadline in the future) is queued
+        let _: () = conn.rpush(format!("lock_queue:{}", lock_name), "someone").unwrap();
+        let _: () = conn
+            .zadd(format!("lock_timeout:{}", lock_name), "someone", now_ms + 60_000)
+            .unwrap();
+
+        let lock = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!lock.is_locked().unwrap());
+        assert!(!lock.try_lock().unwrap(), "Newcomer must not jump the queue");
+
+        // Clean up
+        let _: () = conn
+            .del(&[format!("lock_queue:{}", lock_name), format!("lock_timeout:{}", lock_name)])
+            .unwrap();
+        assert!(lock.try_lock().unwrap());
+        assert!(lock.unlock().unwrap());
+    }
+
+    /// Waiters whose deadline has passed are purged so they cannot block the queue
+    #[test]
+    #[ignore]
+    fn test_fair_lock_purges_stale_waiters() {
+        let lock_name = "FAIR_STALE_LOCK";
+        let client = redis::Client::open(REDIS_URL).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        // A crashed waiter whose deadline already passed
+        let _: () = conn.rpush(format!("lock_queue:{}", lock_name), "crashed").unwrap();
+        let _: () = conn
+            .zadd(format!("lock_timeout:{}", lock_name), "crashed", now_ms - 1)
+            .unwrap();
+
+        let lock = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(lock.try_lock().unwrap(), "Stale waiter should have been purged");
+        assert_eq!(queue_len(lock_name), 0);
+        assert!(lock.unlock().unwrap());
+    }
+
+    /// A waiter that times out leaves the queue
+    #[test]
+    #[ignore]
+    fn test_fair_lock_timed_out_waiter_leaves_queue() {
+        let lock_name = "FAIR_TIMEOUT_LOCK";
+
+        let holder = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(holder.try_lock().unwrap());
+
+        let waiter = RedissonFairLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!waiter.try_lock_for(Duration::from_millis(200), None).unwrap());
+        assert_eq!(queue_len(lock_name), 0, "Timed out waiter should have left the queue");
+
+        // The lock is immediately available to the next comer once released
+        assert!(holder.unlock().unwrap());
+        assert!(waiter.try_lock().unwrap());
+        assert!(waiter.unlock().unwrap());
+    }
+}
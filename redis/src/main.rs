@@ -1,21 +1,139 @@
+pub mod async_lock;
+pub mod countdown_latch;
+pub mod fair_lock;
+pub mod redlock;
+pub mod rw_lock;
+pub mod semaphore;
+
 use redis::{Client, Commands, RedisResult};
 use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
-use std::thread::{self, ThreadId};
+use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// Default lease used while the watchdog is active, same as Redisson's lockWatchdogTimeout
+pub const DEFAULT_WATCHDOG_TIMEOUT_MS: u64 = 30_000;
+
+/// Take the lock if it is free or already held by ARGV[2], and bump that holder's count
+/// A fresh acquisition draws the next fencing token from KEYS[2]; re-entry keeps the
+/// token of the current hold
+/// Returns {1, fencing token} on success, or {0, PTTL} of the lock held by someone else
+const ACQUIRE_SCRIPT: &str = r#"
+    local lock_key = KEYS[1]
+    local fence_key = KEYS[2]
+    local lease_ms = ARGV[1]
+    local holder = ARGV[2]
+
+    if redis.call('EXISTS', lock_key) == 0 then
+        local token = redis.call('INCR', fence_key)
+        redis.call('HSET', lock_key, 'fence', token, holder, 1)
+        redis.call('PEXPIRE', lock_key, lease_ms)
+        return {1, token}
+    end
+    if redis.call('HEXISTS', lock_key, holder) == 1 then
+        redis.call('HINCRBY', lock_key, holder, 1)
+        redis.call('PEXPIRE', lock_key, lease_ms)
+        return {1, tonumber(redis.call('HGET', lock_key, 'fence') or '0')}
+    end
+    return {0, redis.call('PTTL', lock_key)}
+"#;
+
+/// Release one hold of ARGV[2], deleting the key and notifying waiters on KEYS[2]
+/// once the count reaches zero
+/// Returns nil if not the owner, 0 if still held (reentrant), 1 if released
+const RELEASE_SCRIPT: &str = r#"
+    local lock_key = KEYS[1]
+    local channel = KEYS[2]
+    local lease_ms = ARGV[1]
+    local holder = ARGV[2]
+
+    if redis.call('HEXISTS', lock_key, holder) == 0 then
+        return nil
+    end
+
+    local counter = redis.call('HINCRBY', lock_key, holder, -1)
+    if counter > 0 then
+        redis.call('PEXPIRE', lock_key, lease_ms)
+        return 0
+    end
+
+    redis.call('DEL', lock_key)
+    redis.call('PUBLISH', channel, 'unlocked')
+    return 1
+"#;
+
+/// Extend the lease to ARGV[1] ms, only while the lock still carries holder ARGV[2]
+/// Returns 1 if extended, 0 if the holder no longer owns the lock
+const RENEW_SCRIPT: &str = r#"
+    if redis.call('HEXISTS', KEYS[1], ARGV[2]) == 1 then
+        redis.call('PEXPIRE', KEYS[1], ARGV[1])
+        return 1
+    end
+    return 0
+"#;
+
+/// Why the watchdog stopped (or failed) extending a lock's lease
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenewalFailure {
+    /// The lock no longer carries our holder id (expired or taken over)
+    LockLost,
+    /// Redis could not be reached; renewal is retried on the next tick
+    Redis(String),
+}
+
+/// Outcome of a single acquisition attempt, as returned by the acquire scripts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attempt {
+    /// Acquired, with the fencing token of this hold
+    Acquired(u64),
+    /// Held by someone else, with the lock's PTTL
+    Held(i64),
+}
+
+impl redis::FromRedisValue for Attempt {
+    fn from_redis_value(v: &redis::Value) -> RedisResult<Self> {
+        let (acquired, value): (i64, i64) = redis::from_redis_value(v)?;
+        Ok(if acquired == 1 {
+            Attempt::Acquired(value as u64)
+        } else {
+            Attempt::Held(value)
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RedissonLock {
     client: Client,
     lock_name: String,
     uuid: String,
-    lease_time_ms: u64,
-    // Track thread ownership locally
-    thread_ownership: Arc<Mutex<HashMap<String, ThreadId>>>,
+    /// Explicit lease, or None to let the watchdog keep the lock alive
+    lease_time_ms: Option<u64>,
+    watchdog_timeout_ms: u64,
+    // Active watchdogs per holder; dropping a sender stops its renewer thread
+    renewals: Arc<Mutex<HashMap<String, Sender<()>>>>,
+    last_renewal_failure: Arc<Mutex<Option<RenewalFailure>>>,
 }
 
 impl RedissonLock {
     pub fn new(redis_url: &str, lock_name: &str, lease_time_ms: u64) -> RedisResult<Self> {
+        Self::build(redis_url, lock_name, Some(lease_time_ms), DEFAULT_WATCHDOG_TIMEOUT_MS)
+    }
+
+    /// Create a lock without an explicit lease
+    /// While held, a background watchdog extends the lease every `watchdog_timeout_ms / 3`
+    /// until the lock is released or every handle to it is dropped
+    pub fn with_watchdog(redis_url: &str, lock_name: &str, watchdog_timeout_ms: u64) -> RedisResult<Self> {
+        Self::build(redis_url, lock_name, None, watchdog_timeout_ms)
+    }
+
+    fn build(
+        redis_url: &str,
+        lock_name: &str,
+        lease_time_ms: Option<u64>,
+        watchdog_timeout_ms: u64,
+    ) -> RedisResult<Self> {
         let client = Client::open(redis_url)?;
         let uuid = Uuid::new_v4().to_string();
         
@@ -24,107 +142,256 @@ impl RedissonLock {
             lock_name: lock_name.to_string(),
             uuid,
             lease_time_ms,
-            thread_ownership: Arc::new(Mutex::new(HashMap::new())),
+            watchdog_timeout_ms,
+            renewals: Arc::new(Mutex::new(HashMap::new())),
+            last_renewal_failure: Arc::new(Mutex::new(None)),
         })
     }
 
+    /// Lease applied on acquire/release: the explicit one, or the watchdog timeout
+    fn effective_lease_ms(&self) -> u64 {
+        self.lease_time_ms.unwrap_or(self.watchdog_timeout_ms)
+    }
+
+    /// The most recent watchdog renewal failure, if any
+    pub fn last_renewal_failure(&self) -> Option<RenewalFailure> {
+        self.last_renewal_failure.lock().ok().and_then(|f| f.clone())
+    }
+
+    /// Hash field identifying the current holder, as Redisson does: "{uuid}:{thread_id}"
+    fn holder_id(&self) -> String {
+        format!("{}:{:?}", self.uuid, thread::current().id())
+    }
+
     /// Check if the current thread holds the lock
     /// This is the main implementation of Redisson's isHeldByCurrentThread
     pub fn is_held_by_current_thread(&self) -> RedisResult<bool> {
-        let current_thread_id = thread::current().id();
-        
-        // First check local thread ownership tracking
-        if let Ok(ownership_map) = self.thread_ownership.lock() {
-            if let Some(&stored_thread_id) = ownership_map.get(&self.lock_name) {
-                if stored_thread_id == current_thread_id {
-                    // Verify with Redis that the lock is still valid
-                    return self.verify_lock_in_redis();
-                }
-            }
-        }
-        
-        Ok(false)
+        Ok(self.get_hold_count()? > 0)
     }
 
-    /// Verify the lock exists in Redis and belongs to this client instance
-    fn verify_lock_in_redis(&self) -> RedisResult<bool> {
+    /// Number of times the current thread has acquired the lock without releasing it
+    /// This is the equivalent of Redisson's getHoldCount
+    pub fn get_hold_count(&self) -> RedisResult<u32> {
         let mut conn = self.client.get_connection()?;
         let lock_key = format!("lock:{}", self.lock_name);
-        
-        // Check if the lock exists and contains our UUID
-        let lock_value: Option<String> = conn.get(&lock_key)?;
-        
-        match lock_value {
-            Some(value) => {
-                // Parse the lock value to check if it contains our UUID
-                // Redisson typically stores: "{uuid}:{thread_id}:{reentrant_count}"
-                Ok(value.starts_with(&self.uuid))
-            }
-            None => Ok(false),
-        }
+
+        // The lock is a hash of "{uuid}:{thread_id}" -> reentrant count
+        let count: Option<u32> = conn.hget(&lock_key, self.holder_id())?;
+        Ok(count.unwrap_or(0))
     }
 
     /// Acquire the lock (helper method for testing)
+    ///
+    /// Reentrant: if the current thread already holds the lock, its hold count is
+    /// incremented and the lease is reset.
     pub fn try_lock(&self) -> RedisResult<bool> {
+        Ok(matches!(self.try_acquire(self.lease_time_ms)?, Attempt::Acquired(_)))
+    }
+
+    /// Acquire the lock, waiting as long as it takes
+    /// Uses the lease this lock was created with (or the watchdog)
+    pub fn lock(&self) -> RedisResult<()> {
+        self.acquire_waiting(None, self.lease_time_ms)?;
+        Ok(())
+    }
+
+    /// Try to acquire the lock, waiting up to `wait` for the current holder to release it
+    /// With `lease` set to None the watchdog keeps the lock alive until unlock
+    pub fn try_lock_for(&self, wait: Duration, lease: Option<Duration>) -> RedisResult<bool> {
+        let lease_ms = lease.map(|l| l.as_millis() as u64);
+        Ok(self.acquire_waiting(Some(wait), lease_ms)?.is_some())
+    }
+
+    /// Fencing token of the current thread's hold, or None if it does not hold the lock
+    ///
+    /// Tokens come from a per-lock `INCR` counter (`lock_fence:{name}`), so each new holder
+    /// gets a higher one than the last. Pass it along with writes to a downstream store and
+    /// have that store reject tokens lower than the highest it has seen: a holder that
+    /// stalled past its lease is then fenced off even if it still believes it owns the lock.
+    pub fn fencing_token(&self) -> RedisResult<Option<u64>> {
         let mut conn = self.client.get_connection()?;
         let lock_key = format!("lock:{}", self.lock_name);
-        let current_thread_id = thread::current().id();
-        
-        // Create lock value with UUID and thread info
-        let lock_value = format!("{}:{:?}:1", self.uuid, current_thread_id);
-        
-        // Try to acquire lock with expiration
-        let result: bool = redis::cmd("SET")
-            .arg(&lock_key)
-            .arg(&lock_value)
-            .arg("PX")
-            .arg(self.lease_time_ms)
-            .arg("NX")
+
+        let (count, token): (Option<u32>, Option<u64>) = redis::pipe()
+            .hget(&lock_key, self.holder_id())
+            .hget(&lock_key, "fence")
             .query(&mut conn)?;
-            
-        if result {
-            // Update local thread ownership tracking
-            if let Ok(mut ownership_map) = self.thread_ownership.lock() {
-                ownership_map.insert(self.lock_name.clone(), current_thread_id);
-            }
+        Ok(token.filter(|_| count.unwrap_or(0) > 0))
+    }
+
+    /// Single acquisition attempt with the given lease (None = watchdog)
+    fn try_acquire(&self, lease_ms: Option<u64>) -> RedisResult<Attempt> {
+        let mut conn = self.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let attempt: Attempt = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(self.fence_name())
+            .arg(lease_ms.unwrap_or(self.watchdog_timeout_ms))
+            .arg(self.holder_id())
+            .invoke(&mut conn)?;
+
+        if matches!(attempt, Attempt::Acquired(_)) && lease_ms.is_none() {
+            self.schedule_renewal(self.holder_id());
         }
-        
-        Ok(result)
+
+        Ok(attempt)
+    }
+
+    /// Retry acquisition until it succeeds or `wait` runs out (None = forever)
+    /// Returns the fencing token if acquired
+    fn acquire_waiting(&self, wait: Option<Duration>, lease_ms: Option<u64>) -> RedisResult<Option<u64>> {
+        let mut token = None;
+        wait_for_release(&self.client, &self.channel_name(), wait, || {
+            Ok(match self.try_acquire(lease_ms)? {
+                Attempt::Acquired(t) => {
+                    token = Some(t);
+                    None
+                }
+                Attempt::Held(ttl) => Some(ttl),
+            })
+        })?;
+        Ok(token)
+    }
+
+    /// Counter the fencing tokens are drawn from; never expires, so tokens only grow
+    fn fence_name(&self) -> String {
+        format!("lock_fence:{}", self.lock_name)
+    }
+
+    /// Pub/sub channel that unlock publishes to when the lock is released
+    fn channel_name(&self) -> String {
+        format!("lock_channel:{}", self.lock_name)
     }
 
     /// Release the lock (helper method for testing)
+    ///
+    /// Decrements the current thread's hold count and deletes the key once it reaches
+    /// zero. Returns false if the current thread does not hold the lock.
     pub fn unlock(&self) -> RedisResult<bool> {
+        self.release(&self.holder_id())
+    }
+
+    /// Release one hold of `holder`, see `unlock`
+    fn release(&self, holder: &str) -> RedisResult<bool> {
         let mut conn = self.client.get_connection()?;
         let lock_key = format!("lock:{}", self.lock_name);
-        let _current_thread_id = thread::current().id();
-        
-        // Lua script to safely release lock only if owned by current client
-        let lua_script = r#"
-            local lock_key = KEYS[1]
-            local expected_prefix = ARGV[1]
-            local current_value = redis.call('GET', lock_key)
-            
-            if current_value and string.sub(current_value, 1, string.len(expected_prefix)) == expected_prefix then
-                redis.call('DEL', lock_key)
-                return 1
-            else
-                return 0
-            end
-        "#;
         
-        let result: i32 = redis::Script::new(lua_script)
+        let result: Option<i32> = redis::Script::new(RELEASE_SCRIPT)
             .key(&lock_key)
-            .arg(&self.uuid)
+            .key(self.channel_name())
+            .arg(self.effective_lease_ms())
+            .arg(holder)
             .invoke(&mut conn)?;
-            
-        if result == 1 {
-            // Update local thread ownership tracking
-            if let Ok(mut ownership_map) = self.thread_ownership.lock() {
-                ownership_map.remove(&self.lock_name);
+
+        // Fully released, or not ours anymore: nothing left for the watchdog to renew
+        if result != Some(0) {
+            self.cancel_renewal(holder);
+        }
+
+        Ok(result.is_some())
+    }
+
+    /// Acquire the lock without waiting, returning a guard that releases it on drop
+    pub fn try_lock_guarded(&self) -> RedisResult<Option<RedissonLockGuard<'_>>> {
+        Ok(match self.try_acquire(self.lease_time_ms)? {
+            Attempt::Acquired(token) => Some(self.guard(token)),
+            Attempt::Held(_) => None,
+        })
+    }
+
+    /// Acquire the lock, waiting as long as it takes, and return a guard that releases it on drop
+    pub fn lock_guarded(&self) -> RedisResult<RedissonLockGuard<'_>> {
+        let token = self
+            .acquire_waiting(None, self.lease_time_ms)?
+            .expect("waiting without a deadline only returns once acquired");
+        Ok(self.guard(token))
+    }
+
+    /// Like `try_lock_for`, returning a guard that releases the lock on drop
+    pub fn try_lock_for_guarded(
+        &self,
+        wait: Duration,
+        lease: Option<Duration>,
+    ) -> RedisResult<Option<RedissonLockGuard<'_>>> {
+        let lease_ms = lease.map(|l| l.as_millis() as u64);
+        Ok(self.acquire_waiting(Some(wait), lease_ms)?.map(|token| self.guard(token)))
+    }
+
+    /// Guard for a hold the current thread has just taken
+    fn guard(&self, fencing_token: u64) -> RedissonLockGuard<'_> {
+        RedissonLockGuard {
+            lock: self,
+            holder: self.holder_id(),
+            fencing_token,
+            released: false,
+        }
+    }
+
+    /// Start the watchdog for `holder` unless one is already running (reentrant acquire)
+    fn schedule_renewal(&self, holder: String) {
+        let Ok(mut renewals) = self.renewals.lock() else {
+            return;
+        };
+        if renewals.contains_key(&holder) {
+            return;
+        }
+
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        renewals.insert(holder.clone(), stop_tx);
+
+        let client = self.client.clone();
+        let lock_key = format!("lock:{}", self.lock_name);
+        let timeout_ms = self.watchdog_timeout_ms;
+        let interval = Duration::from_millis((timeout_ms / 3).max(1));
+        let last_failure = Arc::downgrade(&self.last_renewal_failure);
+
+        thread::spawn(move || {
+            // Extend the lease only while the lock still carries our holder id
+            let lua_script = redis::Script::new(RENEW_SCRIPT);
+            let mut failing = false;
+
+            // Stops on an explicit cancel, or once every handle (and so the sender) is dropped
+            while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                let renewed: RedisResult<i32> = client.get_connection().and_then(|mut conn| {
+                    lua_script
+                        .key(&lock_key)
+                        .arg(timeout_ms)
+                        .arg(&holder)
+                        .invoke(&mut conn)
+                });
+
+                let failure = match renewed {
+                    Ok(1) => {
+                        failing = false;
+                        continue;
+                    }
+                    Ok(_) => RenewalFailure::LockLost,
+                    Err(e) => RenewalFailure::Redis(e.to_string()),
+                };
+
+                // Log once per outage rather than on every tick; callers poll
+                // `last_renewal_failure` for the details
+                if !failing {
+                    tracing::warn!(lock = %lock_key, ?failure, "watchdog failed to renew lease");
+                    failing = true;
+                }
+                if let Some(last_failure) = last_failure.upgrade() {
+                    if let Ok(mut last_failure) = last_failure.lock() {
+                        *last_failure = Some(failure);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Stop the watchdog for `holder`, if any
+    fn cancel_renewal(&self, holder: &str) {
+        if let Ok(mut renewals) = self.renewals.lock() {
+            if let Some(stop_tx) = renewals.remove(holder) {
+                let _ = stop_tx.send(());
             }
         }
-        
-        Ok(result == 1)
     }
 
     /// Check if any thread holds the lock (not necessarily current thread)
@@ -146,9 +413,116 @@ impl RedissonLock {
     }
 }
 
+/// Retry `attempt` until it succeeds or `wait` runs out (None = forever)
+///
+/// `attempt` returns None once acquired, or the PTTL of the lock held by someone else.
+/// Between attempts we block on the lock's pub/sub channel, which unlock publishes
+/// to, so a waiter wakes as soon as the lock is released. The wait per attempt is
+/// capped by the holder's PTTL in case the lease simply expires.
+fn wait_for_release(
+    client: &Client,
+    channel: &str,
+    wait: Option<Duration>,
+    mut attempt: impl FnMut() -> RedisResult<Option<i64>>,
+) -> RedisResult<bool> {
+    let deadline = wait.map(|w| Instant::now() + w);
+
+    if attempt()?.is_none() {
+        return Ok(true);
+    }
+
+    // Subscribe before retrying so a release between the two cannot be missed
+    let mut conn = client.get_connection()?;
+    let mut pubsub = conn.as_pubsub();
+    pubsub.subscribe(channel)?;
+
+    loop {
+        let ttl = match attempt()? {
+            None => return Ok(true),
+            Some(ttl) => ttl,
+        };
+
+        // PTTL is negative if the key vanished meanwhile: just retry soon
+        let mut timeout = Duration::from_millis(ttl.max(1) as u64);
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            timeout = timeout.min(remaining);
+        }
+
+        pubsub.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        match pubsub.get_message() {
+            Ok(_) => {}
+            Err(e) if e.is_timeout() => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// One hold of a `RedissonLock`, released when the guard is dropped
+///
+/// The holder id is captured at acquisition, so the hold is released even if the guard
+/// ends up being dropped during a panic unwind.
+#[derive(Debug)]
+pub struct RedissonLockGuard<'a> {
+    lock: &'a RedissonLock,
+    holder: String,
+    fencing_token: u64,
+    released: bool,
+}
+
+impl RedissonLockGuard<'_> {
+    /// Fencing token of this hold, see `RedissonLock::fencing_token`
+    pub fn fencing_token(&self) -> u64 {
+        self.fencing_token
+    }
+
+    /// Remaining time to live of the lock in milliseconds
+    pub fn remaining_time_to_live(&self) -> RedisResult<i64> {
+        self.lock.get_remaining_time_to_live()
+    }
+
+    /// Reset the lease to `lease` from now, as long as we still hold the lock
+    /// Returns false if the lock was lost in the meantime
+    ///
+    /// With the watchdog active, the next renewal sets the lease back to the watchdog timeout.
+    pub fn extend(&self, lease: Duration) -> RedisResult<bool> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        let extended: i32 = redis::Script::new(RENEW_SCRIPT)
+            .key(&lock_key)
+            .arg(lease.as_millis() as u64)
+            .arg(&self.holder)
+            .invoke(&mut conn)?;
+        Ok(extended == 1)
+    }
+
+    /// Release the hold now, reporting errors that drop would have to swallow
+    pub fn unlock(mut self) -> RedisResult<bool> {
+        self.released = true;
+        self.lock.release(&self.holder)
+    }
+}
+
+impl Drop for RedissonLockGuard<'_> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        // Drop cannot return the error; `unlock()` does
+        if let Err(e) = self.lock.release(&self.holder) {
+            tracing::warn!(lock = %self.lock.lock_name, error = %e, "failed to release lock on drop");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
@@ -436,6 +810,361 @@ mod tests {
         // Clean up
         assert!(new_lock.unlock().unwrap());
     }
+
+    /// Test reentrant acquisition: nested try_lock/unlock pairs on the same thread
+    #[test]
+    #[ignore]
+    fn test_reentrant_lock_nested_acquire_release() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "REENTRANT_TEST_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert_eq!(lock.get_hold_count().unwrap(), 0);
+
+        // Same thread can take the lock several times
+        assert!(lock.try_lock().unwrap());
+        assert!(lock.try_lock().unwrap());
+        assert!(lock.try_lock().unwrap());
+        assert_eq!(lock.get_hold_count().unwrap(), 3);
+
+        // Another client cannot take it while it is held
+        let other = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(!other.try_lock().unwrap());
+
+        // Each unlock releases one hold; the key stays until the last one
+        assert!(lock.unlock().unwrap());
+        assert_eq!(lock.get_hold_count().unwrap(), 2);
+        assert!(lock.is_locked().unwrap());
+
+        assert!(lock.unlock().unwrap());
+        assert_eq!(lock.get_hold_count().unwrap(), 1);
+        assert!(lock.is_held_by_current_thread().unwrap());
+
+        assert!(lock.unlock().unwrap());
+        assert_eq!(lock.get_hold_count().unwrap(), 0);
+        assert!(!lock.is_locked().unwrap());
+
+        // Unlocking more times than locked is rejected
+        assert!(!lock.unlock().unwrap());
+    }
+
+    /// Test that a thread which does not own the lock cannot release it
+    #[test]
+    #[ignore]
+    fn test_unlock_by_non_owner_thread() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "NON_OWNER_UNLOCK_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(lock.try_lock().unwrap());
+
+        // Same client instance (same uuid), different thread
+        let lock_clone = lock.clone();
+        let handle = thread::spawn(move || lock_clone.unlock().unwrap());
+        assert!(!handle.join().unwrap(), "Non-owner thread should not be able to unlock");
+
+        // Different client instance on the same thread
+        let other = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(!other.unlock().unwrap(), "Other client should not be able to unlock");
+
+        // The owner still holds the lock with its original count
+        assert!(lock.is_locked().unwrap());
+        assert_eq!(lock.get_hold_count().unwrap(), 1);
+
+        // Clean up
+        assert!(lock.unlock().unwrap());
+        assert!(!lock.is_locked().unwrap());
+    }
+
+    /// Test that the watchdog keeps a lock without explicit lease alive past its timeout
+    #[test]
+    #[ignore]
+    fn test_watchdog_renews_lease() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "WATCHDOG_RENEW_LOCK";
+        let watchdog_timeout_ms = 300;
+
+        let lock = RedissonLock::with_watchdog(redis_url, lock_name, watchdog_timeout_ms).unwrap();
+        assert!(lock.try_lock().unwrap());
+
+        // Well past the watchdog timeout, the lock must still be ours
+        thread::sleep(Duration::from_millis(watchdog_timeout_ms * 4));
+        assert!(lock.is_held_by_current_thread().unwrap(), "Watchdog should have renewed the lease");
+        assert!(lock.get_remaining_time_to_live().unwrap() > 0);
+        assert_eq!(lock.last_renewal_failure(), None);
+
+        // After unlock the watchdog stops and the lock stays released
+        assert!(lock.unlock().unwrap());
+        thread::sleep(Duration::from_millis(watchdog_timeout_ms));
+        assert!(!lock.is_locked().unwrap());
+    }
+
+    /// Test that dropping every handle stops the watchdog so the lease can expire
+    #[test]
+    #[ignore]
+    fn test_watchdog_stops_when_handle_dropped() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "WATCHDOG_DROP_LOCK";
+        let watchdog_timeout_ms = 300;
+
+        let lock = RedissonLock::with_watchdog(redis_url, lock_name, watchdog_timeout_ms).unwrap();
+        assert!(lock.try_lock().unwrap());
+        drop(lock);
+
+        thread::sleep(Duration::from_millis(watchdog_timeout_ms * 2));
+        let observer = RedissonLock::new(redis_url, lock_name, 1000).unwrap();
+        assert!(!observer.is_locked().unwrap(), "Lease should expire once the watchdog is gone");
+    }
+
+    /// Test that the watchdog reports when the lock was lost behind its back
+    #[test]
+    #[ignore]
+    fn test_watchdog_reports_lost_lock() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "WATCHDOG_LOST_LOCK";
+        let watchdog_timeout_ms = 300;
+
+        let lock = RedissonLock::with_watchdog(redis_url, lock_name, watchdog_timeout_ms).unwrap();
+        assert!(lock.try_lock().unwrap());
+
+        // Someone deletes the key out from under us
+        let client = Client::open(redis_url).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let _: () = conn.del(format!("lock:{}", lock_name)).unwrap();
+
+        thread::sleep(Duration::from_millis(watchdog_timeout_ms));
+        assert_eq!(lock.last_renewal_failure(), Some(RenewalFailure::LockLost));
+        assert!(!lock.is_locked().unwrap(), "Watchdog must not resurrect a lost lock");
+
+        // Unlocking a lost lock fails but still shuts the watchdog down
+        assert!(!lock.unlock().unwrap());
+    }
+
+    /// Test that lock() blocks until the holder releases, and wakes promptly on unlock
+    #[test]
+    #[ignore]
+    fn test_lock_waits_for_release() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "BLOCKING_LOCK";
+
+        let holder = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(holder.try_lock().unwrap());
+
+        let (tx, rx) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+            let started = Instant::now();
+            lock.lock().unwrap();
+            tx.send(started.elapsed()).unwrap();
+            assert!(lock.unlock().unwrap());
+        });
+
+        // Waiter must not get the lock while it is held
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+        assert!(holder.unlock().unwrap());
+
+        // Woken by the unlock publish, long before the 30s lease would expire
+        let waited = rx.recv_timeout(Duration::from_secs(2)).expect("waiter should acquire after unlock");
+        assert!(waited < Duration::from_secs(2), "Waiter took {:?}", waited);
+        waiter.join().unwrap();
+    }
+
+    /// Test that try_lock_for gives up once the wait time elapses
+    #[test]
+    #[ignore]
+    fn test_try_lock_for_times_out() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "TIMED_LOCK";
+
+        let holder = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(holder.try_lock().unwrap());
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        let started = Instant::now();
+        let acquired = lock.try_lock_for(Duration::from_millis(300), None).unwrap();
+        let waited = started.elapsed();
+
+        assert!(!acquired, "Lock is held, try_lock_for should time out");
+        assert!(waited >= Duration::from_millis(300), "Gave up too early: {:?}", waited);
+        assert!(waited < Duration::from_secs(2), "Gave up too late: {:?}", waited);
+
+        // Clean up
+        assert!(holder.unlock().unwrap());
+    }
+
+    /// Test that try_lock_for picks up a lock whose lease expires without an unlock
+    #[test]
+    #[ignore]
+    fn test_try_lock_for_after_lease_expiry() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "TIMED_EXPIRY_LOCK";
+
+        let holder = RedissonLock::new(redis_url, lock_name, 300).unwrap();
+        assert!(holder.try_lock().unwrap());
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(lock
+            .try_lock_for(Duration::from_secs(2), Some(Duration::from_millis(5000)))
+            .unwrap());
+        assert!(lock.is_held_by_current_thread().unwrap());
+        assert!(lock.get_remaining_time_to_live().unwrap() > 300);
+
+        // Clean up
+        assert!(lock.unlock().unwrap());
+    }
+
+    /// Test that contending threads all get the lock in turn with lock()
+    #[test]
+    #[ignore]
+    fn test_lock_serializes_contending_threads() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "BLOCKING_CONTENTION_LOCK";
+        let num_threads = 5;
+        let in_critical_section = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| {
+                let in_critical_section = in_critical_section.clone();
+                thread::spawn(move || {
+                    let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+                    lock.lock().unwrap();
+                    let others = in_critical_section.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(others, 0, "Two threads inside the critical section");
+                    thread::sleep(Duration::from_millis(50));
+                    in_critical_section.fetch_sub(1, Ordering::SeqCst);
+                    assert!(lock.unlock().unwrap());
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Test that dropping the guard releases the lock
+    #[test]
+    #[ignore]
+    fn test_guard_releases_on_drop() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "GUARD_DROP_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        {
+            let guard = lock.try_lock_guarded().unwrap().expect("lock should be free");
+            assert!(lock.is_held_by_current_thread().unwrap());
+            assert!(guard.remaining_time_to_live().unwrap() > 0);
+
+            // Nested guards each hold the reentrant lock once
+            let inner = lock.lock_guarded().unwrap();
+            assert_eq!(lock.get_hold_count().unwrap(), 2);
+            drop(inner);
+            assert_eq!(lock.get_hold_count().unwrap(), 1);
+        }
+
+        assert!(!lock.is_locked().unwrap(), "Guard should release the lock on drop");
+    }
+
+    /// Test that a panic inside the critical section does not leave the lock held
+    #[test]
+    #[ignore]
+    fn test_guard_releases_on_panic() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "GUARD_PANIC_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        let lock_clone = lock.clone();
+        let result = thread::spawn(move || {
+            let _guard = lock_clone.try_lock_guarded().unwrap().expect("lock should be free");
+            panic!("boom inside the critical section");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(!lock.is_locked().unwrap(), "Lock must not survive a panicking holder");
+    }
+
+    /// Test explicit lease extension and explicit unlock through the guard
+    #[test]
+    #[ignore]
+    fn test_guard_extend_and_unlock() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "GUARD_EXTEND_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 500).unwrap();
+        let guard = lock.try_lock_guarded().unwrap().expect("lock should be free");
+        assert!(guard.remaining_time_to_live().unwrap() <= 500);
+
+        assert!(guard.extend(Duration::from_secs(10)).unwrap());
+        assert!(guard.remaining_time_to_live().unwrap() > 500);
+
+        // Outlives the original lease thanks to the extension
+        thread::sleep(Duration::from_millis(700));
+        assert!(lock.is_held_by_current_thread().unwrap());
+
+        assert!(guard.unlock().unwrap());
+        assert!(!lock.is_locked().unwrap());
+
+        // A guard cannot extend a lock it has lost
+        let guard = lock.try_lock_guarded().unwrap().expect("lock should be free");
+        thread::sleep(Duration::from_millis(700));
+        assert!(!guard.extend(Duration::from_secs(10)).unwrap());
+        assert!(!guard.unlock().unwrap());
+    }
+
+    /// Test that a contended lock yields no guard
+    #[test]
+    #[ignore]
+    fn test_guard_not_returned_when_contended() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "GUARD_CONTENDED_LOCK";
+
+        let holder = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        let _guard = holder.try_lock_guarded().unwrap().expect("lock should be free");
+
+        let other = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert!(other.try_lock_guarded().unwrap().is_none());
+        assert!(other
+            .try_lock_for_guarded(Duration::from_millis(100), None)
+            .unwrap()
+            .is_none());
+    }
+
+    /// Test that each new holder gets a higher fencing token, and re-entry keeps it
+    #[test]
+    #[ignore]
+    fn test_fencing_tokens_increase() {
+        let redis_url = "redis://127.0.0.1:6379/";
+        let lock_name = "FENCING_TOKEN_LOCK";
+
+        let lock = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        assert_eq!(lock.fencing_token().unwrap(), None, "No token without a hold");
+
+        let first = lock.try_lock_guarded().unwrap().expect("lock should be free");
+        let token = first.fencing_token();
+        assert_eq!(lock.fencing_token().unwrap(), Some(token));
+
+        // Re-entry is the same hold, so it keeps the token
+        let inner = lock.lock_guarded().unwrap();
+        assert_eq!(inner.fencing_token(), token);
+        drop(inner);
+        drop(first);
+
+        // Another holder sees the token but does not own it
+        let other = RedissonLock::new(redis_url, lock_name, 30000).unwrap();
+        let second = other.try_lock_guarded().unwrap().expect("lock should be free");
+        assert!(second.fencing_token() > token, "Tokens must increase across holders");
+        assert_eq!(lock.fencing_token().unwrap(), None);
+        drop(second);
+
+        // Also across a holder whose lease ran out
+        let expiring = RedissonLock::new(redis_url, lock_name, 100).unwrap();
+        assert!(expiring.try_lock().unwrap());
+        let stale = expiring.fencing_token().unwrap().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let third = lock.try_lock_guarded().unwrap().expect("lease should have expired");
+        assert!(third.fencing_token() > stale);
+    }
 }
 
 // Example usage
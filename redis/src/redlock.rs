@@ -0,0 +1,259 @@
+use redis::{Client, Commands, Connection, RedisResult};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::{Attempt, ACQUIRE_SCRIPT, RELEASE_SCRIPT};
+
+/// Share of the lease assumed lost to clock drift between nodes, as in the Redlock spec
+pub const CLOCK_DRIFT_FACTOR: f64 = 0.01;
+
+/// Per-node connect/read/write timeout, kept far below the lease so a dead node
+/// cannot eat the validity window
+pub const DEFAULT_NODE_TIMEOUT_MS: u64 = 50;
+
+/// Redlock: a lock held on a majority of N independent Redis nodes
+///
+/// Each node stores the same `lock:{name}` hash as `RedissonLock`, so a single failover
+/// cannot hand the lock to two clients: the new holder would need a majority too.
+/// Fencing tokens are drawn per node and so do not order holders across the cluster;
+/// they are not exposed here.
+#[derive(Debug, Clone)]
+pub struct RedissonRedLock {
+    clients: Vec<Client>,
+    lock_name: String,
+    uuid: String,
+    lease_time_ms: u64,
+    node_timeout: Duration,
+}
+
+impl RedissonRedLock {
+    pub fn new(redis_urls: &[&str], lock_name: &str, lease_time_ms: u64) -> RedisResult<Self> {
+        let clients = redis_urls
+            .iter()
+            .map(|url| Client::open(*url))
+            .collect::<RedisResult<Vec<_>>>()?;
+
+        Ok(RedissonRedLock {
+            clients,
+            lock_name: lock_name.to_string(),
+            uuid: Uuid::new_v4().to_string(),
+            lease_time_ms,
+            node_timeout: Duration::from_millis(DEFAULT_NODE_TIMEOUT_MS),
+        })
+    }
+
+    /// Override the per-node timeout
+    pub fn with_node_timeout(mut self, node_timeout: Duration) -> Self {
+        self.node_timeout = node_timeout;
+        self
+    }
+
+    /// Number of nodes that must agree: a strict majority
+    pub fn quorum(&self) -> usize {
+        self.clients.len() / 2 + 1
+    }
+
+    fn holder_id(&self) -> String {
+        format!("{}:{:?}", self.uuid, thread::current().id())
+    }
+
+    /// Try to acquire the lock on a majority of nodes
+    ///
+    /// Returns the validity time: the lease minus the time spent acquiring and the
+    /// allowed clock drift. The lock must only be relied on within that window. If no
+    /// majority is reached in time, the lock is released on every node: one that errored
+    /// or timed out may still have run the script and hold it for us.
+    pub fn try_lock(&self) -> RedisResult<Option<Duration>> {
+        let holder = self.holder_id();
+        let started = Instant::now();
+
+        // An unreachable node simply does not count towards the quorum
+        let locked = self
+            .clients
+            .iter()
+            .filter(|client| matches!(self.acquire_on(client, &holder), Ok(true)))
+            .count();
+
+        let drift = Duration::from_millis((self.lease_time_ms as f64 * CLOCK_DRIFT_FACTOR) as u64 + 2);
+        let validity = Duration::from_millis(self.lease_time_ms)
+            .checked_sub(started.elapsed())
+            .and_then(|v| v.checked_sub(drift));
+
+        match validity {
+            Some(validity) if locked >= self.quorum() && !validity.is_zero() => Ok(Some(validity)),
+            _ => {
+                for client in &self.clients {
+                    let _ = self.release_on(client, &holder);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Release the lock on every node, including ones we may have failed to reach on acquire
+    /// Returns true if the current thread held it on a majority of nodes
+    pub fn unlock(&self) -> RedisResult<bool> {
+        let holder = self.holder_id();
+        let released = self
+            .clients
+            .iter()
+            .filter(|client| matches!(self.release_on(client, &holder), Ok(true)))
+            .count();
+
+        Ok(released >= self.quorum())
+    }
+
+    /// Check if a majority of nodes have the lock held by anyone
+    pub fn is_locked(&self) -> RedisResult<bool> {
+        let lock_key = format!("lock:{}", self.lock_name);
+        let locked = self
+            .clients
+            .iter()
+            .filter(|client| {
+                self.connect(client)
+                    .and_then(|mut conn| conn.exists::<_, bool>(&lock_key))
+                    .unwrap_or(false)
+            })
+            .count();
+
+        Ok(locked >= self.quorum())
+    }
+
+    /// Check if a majority of nodes have the lock held by the current thread
+    pub fn is_held_by_current_thread(&self) -> RedisResult<bool> {
+        let lock_key = format!("lock:{}", self.lock_name);
+        let holder = self.holder_id();
+        let held = self
+            .clients
+            .iter()
+            .filter(|client| {
+                self.connect(client)
+                    .and_then(|mut conn| conn.hexists::<_, _, bool>(&lock_key, &holder))
+                    .unwrap_or(false)
+            })
+            .count();
+
+        Ok(held >= self.quorum())
+    }
+
+    fn connect(&self, client: &Client) -> RedisResult<Connection> {
+        let conn = client.get_connection_with_timeout(self.node_timeout)?;
+        conn.set_read_timeout(Some(self.node_timeout))?;
+        conn.set_write_timeout(Some(self.node_timeout))?;
+        Ok(conn)
+    }
+
+    fn acquire_on(&self, client: &Client, holder: &str) -> RedisResult<bool> {
+        let mut conn = self.connect(client)?;
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let attempt: Attempt = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(format!("lock_fence:{}", self.lock_name))
+            .arg(self.lease_time_ms)
+            .arg(holder)
+            .invoke(&mut conn)?;
+        Ok(matches!(attempt, Attempt::Acquired(_)))
+    }
+
+    fn release_on(&self, client: &Client, holder: &str) -> RedisResult<bool> {
+        let mut conn = self.connect(client)?;
+        let lock_key = format!("lock:{}", self.lock_name);
+
+        let result: Option<i32> = redis::Script::new(RELEASE_SCRIPT)
+            .key(&lock_key)
+            .key(format!("lock_channel:{}", self.lock_name))
+            .arg(self.lease_time_ms)
+            .arg(holder)
+            .invoke(&mut conn)?;
+        Ok(result.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three independent nodes: `docker compose up redis redis-node-2 redis-node-3`, or
+    // `redis-server --port 6379`, `--port 6380` and `--port 6381`
+    const NODES: [&str; 3] = [
+        "redis://127.0.0.1:6379/",
+        "redis://127.0.0.1:6380/",
+        "redis://127.0.0.1:6381/",
+    ];
+    // Nothing listens here: stands in for a node that is down
+    const DEAD_NODE: &str = "redis://127.0.0.1:6399/";
+
+    fn node_has_lock(url: &str, lock_name: &str) -> bool {
+        let client = Client::open(url).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        conn.exists(format!("lock:{}", lock_name)).unwrap()
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redlock_acquire_and_release_on_all_nodes() {
+        let lock_name = "REDLOCK_ALL_NODES";
+        let lease_ms = 10_000;
+
+        let lock = RedissonRedLock::new(&NODES, lock_name, lease_ms).unwrap();
+        let validity = lock.try_lock().unwrap().expect("all nodes are free");
+
+        // Validity is the lease minus acquisition time and clock drift
+        assert!(validity < Duration::from_millis(lease_ms - (lease_ms as f64 * CLOCK_DRIFT_FACTOR) as u64));
+        assert!(lock.is_locked().unwrap());
+        assert!(lock.is_held_by_current_thread().unwrap());
+        assert!(NODES.iter().all(|url| node_has_lock(url, lock_name)));
+
+        // Another client cannot reach a majority
+        let other = RedissonRedLock::new(&NODES, lock_name, lease_ms).unwrap();
+        assert!(other.try_lock().unwrap().is_none());
+        assert!(!other.unlock().unwrap());
+
+        assert!(lock.unlock().unwrap());
+        assert!(NODES.iter().all(|url| !node_has_lock(url, lock_name)));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redlock_tolerates_minority_failure() {
+        let lock_name = "REDLOCK_MINORITY_DOWN";
+        let urls = [NODES[0], NODES[1], DEAD_NODE];
+
+        let lock = RedissonRedLock::new(&urls, lock_name, 10_000).unwrap();
+        assert_eq!(lock.quorum(), 2);
+        assert!(lock.try_lock().unwrap().is_some(), "Two of three nodes are enough");
+        assert!(lock.unlock().unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redlock_fails_without_majority_and_cleans_up() {
+        let lock_name = "REDLOCK_MAJORITY_DOWN";
+        let urls = [NODES[0], DEAD_NODE, "redis://127.0.0.1:6398/"];
+
+        let lock = RedissonRedLock::new(&urls, lock_name, 10_000).unwrap();
+        assert!(lock.try_lock().unwrap().is_none(), "One of three nodes is not a majority");
+        assert!(
+            !node_has_lock(NODES[0], lock_name),
+            "Partial acquisition must be rolled back"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_redlock_majority_held_elsewhere() {
+        let lock_name = "REDLOCK_CONTENDED";
+
+        // Someone holds the lock on two of the three nodes
+        let partial = RedissonRedLock::new(&NODES[..2], lock_name, 10_000).unwrap();
+        assert!(partial.try_lock().unwrap().is_some());
+
+        let lock = RedissonRedLock::new(&NODES, lock_name, 10_000).unwrap();
+        assert!(lock.try_lock().unwrap().is_none());
+        assert!(!node_has_lock(NODES[2], lock_name), "Third node must be released again");
+
+        assert!(partial.unlock().unwrap());
+    }
+}
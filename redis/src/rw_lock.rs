@@ -0,0 +1,509 @@
+use redis::{Commands, RedisResult};
+use std::time::Duration;
+
+use crate::{wait_for_release, RedissonLock};
+
+/// Shared prelude for the read/write lock scripts
+///
+/// Reads the server clock, defines `max_reader_ttl()` and drops read holds whose
+/// individual lease has passed, deleting the lock once the last reader is gone.
+/// KEYS[1] is the lock hash, KEYS[2] the reader lease zset.
+macro_rules! rw_lock_prelude {
+    () => {
+        r#"
+        local lock_key = KEYS[1]
+        local readers_key = KEYS[2]
+        local time = redis.call('TIME')
+        local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+        local function max_reader_ttl()
+            local last = redis.call('ZRANGE', readers_key, -1, -1, 'WITHSCORES')
+            if #last == 0 then
+                return 0
+            end
+            return tonumber(last[2]) - now_ms
+        end
+
+        local expired = redis.call('ZRANGEBYSCORE', readers_key, '-inf', now_ms)
+        for i = 1, #expired do
+            redis.call('HDEL', lock_key, expired[i])
+            redis.call('ZREM', readers_key, expired[i])
+        end
+        if redis.call('HGET', lock_key, 'mode') == 'read' and redis.call('ZCARD', readers_key) == 0 then
+            redis.call('DEL', lock_key)
+        end
+        "#
+    };
+}
+
+/// Take a read hold if nobody writes, or if the writer is us (downgrade)
+/// ARGV: lease ms, holder, holder's write field
+/// Returns nil on success, or the PTTL of the write lock
+const READ_ACQUIRE_SCRIPT: &str = concat!(
+    rw_lock_prelude!(),
+    r#"
+    local lease_ms = tonumber(ARGV[1])
+    local holder = ARGV[2]
+    local mode = redis.call('HGET', lock_key, 'mode')
+
+    if mode == false or mode == 'read' or redis.call('HEXISTS', lock_key, ARGV[3]) == 1 then
+        if mode == false then
+            redis.call('HSET', lock_key, 'mode', 'read')
+        end
+        redis.call('HINCRBY', lock_key, holder, 1)
+        redis.call('ZADD', readers_key, now_ms + lease_ms, holder)
+        local ttl = math.max(redis.call('PTTL', lock_key), max_reader_ttl())
+        redis.call('PEXPIRE', lock_key, ttl)
+        redis.call('PEXPIRE', readers_key, ttl)
+        return nil
+    end
+    return redis.call('PTTL', lock_key)
+    "#
+);
+
+/// Take (or re-enter) the write hold if nobody else holds the lock
+/// ARGV: lease ms, holder's write field
+/// Returns nil on success, or the PTTL of the lock
+const WRITE_ACQUIRE_SCRIPT: &str = concat!(
+    rw_lock_prelude!(),
+    r#"
+    local lease_ms = tonumber(ARGV[1])
+    local writer = ARGV[2]
+    local mode = redis.call('HGET', lock_key, 'mode')
+
+    if mode == false then
+        redis.call('HSET', lock_key, 'mode', 'write')
+        redis.call('HSET', lock_key, writer, 1)
+        redis.call('PEXPIRE', lock_key, lease_ms)
+        return nil
+    end
+    if mode == 'write' and redis.call('HEXISTS', lock_key, writer) == 1 then
+        redis.call('HINCRBY', lock_key, writer, 1)
+        redis.call('PEXPIRE', lock_key, math.max(lease_ms, max_reader_ttl()))
+        return nil
+    end
+    return redis.call('PTTL', lock_key)
+    "#
+);
+
+/// Release one read hold; the lock is deleted with the last reader (unless we are
+/// also the writer)
+/// KEYS[3]: lock channel. ARGV: holder
+/// Returns nil if not a reader, 0 if still held (reentrant), 1 if released
+const READ_RELEASE_SCRIPT: &str = concat!(
+    rw_lock_prelude!(),
+    r#"
+    local holder = ARGV[1]
+    if redis.call('HEXISTS', lock_key, holder) == 0 then
+        return nil
+    end
+    if redis.call('HINCRBY', lock_key, holder, -1) > 0 then
+        return 0
+    end
+
+    redis.call('HDEL', lock_key, holder)
+    redis.call('ZREM', readers_key, holder)
+    if redis.call('HGET', lock_key, 'mode') == 'read' then
+        if redis.call('ZCARD', readers_key) == 0 then
+            redis.call('DEL', lock_key, readers_key)
+        else
+            local ttl = max_reader_ttl()
+            redis.call('PEXPIRE', lock_key, ttl)
+            redis.call('PEXPIRE', readers_key, ttl)
+        end
+    end
+    redis.call('PUBLISH', KEYS[3], 'unlocked')
+    return 1
+    "#
+);
+
+/// Release one write hold; if our own read holds remain the lock drops to read mode
+/// KEYS[3]: lock channel. ARGV: holder's write field
+/// Returns nil if not the writer, 0 if still held (reentrant), 1 if released
+const WRITE_RELEASE_SCRIPT: &str = concat!(
+    rw_lock_prelude!(),
+    r#"
+    local writer = ARGV[1]
+    if redis.call('HGET', lock_key, 'mode') ~= 'write' or redis.call('HEXISTS', lock_key, writer) == 0 then
+        return nil
+    end
+    if redis.call('HINCRBY', lock_key, writer, -1) > 0 then
+        return 0
+    end
+
+    redis.call('HDEL', lock_key, writer)
+    if redis.call('ZCARD', readers_key) > 0 then
+        redis.call('HSET', lock_key, 'mode', 'read')
+        local ttl = max_reader_ttl()
+        redis.call('PEXPIRE', lock_key, ttl)
+        redis.call('PEXPIRE', readers_key, ttl)
+    else
+        redis.call('DEL', lock_key)
+    end
+    redis.call('PUBLISH', KEYS[3], 'unlocked')
+    return 1
+    "#
+);
+
+/// Turn one write hold into a read hold in a single step: add the read hold and its
+/// lease, then release one write hold, dropping to read mode with the last one
+/// KEYS[3]: lock channel. ARGV: lease ms, holder, holder's write field
+/// Returns nil if not the writer, 0 if write holds remain (reentrant), 1 if now read mode
+const DOWNGRADE_SCRIPT: &str = concat!(
+    rw_lock_prelude!(),
+    r#"
+    local lease_ms = tonumber(ARGV[1])
+    local holder = ARGV[2]
+    local writer = ARGV[3]
+    if redis.call('HGET', lock_key, 'mode') ~= 'write' or redis.call('HEXISTS', lock_key, writer) == 0 then
+        return nil
+    end
+
+    redis.call('HINCRBY', lock_key, holder, 1)
+    redis.call('ZADD', readers_key, now_ms + lease_ms, holder)
+    if redis.call('HINCRBY', lock_key, writer, -1) > 0 then
+        local ttl = math.max(redis.call('PTTL', lock_key), max_reader_ttl())
+        redis.call('PEXPIRE', lock_key, ttl)
+        redis.call('PEXPIRE', readers_key, ttl)
+        return 0
+    end
+
+    redis.call('HDEL', lock_key, writer)
+    redis.call('HSET', lock_key, 'mode', 'read')
+    local ttl = max_reader_ttl()
+    redis.call('PEXPIRE', lock_key, ttl)
+    redis.call('PEXPIRE', readers_key, ttl)
+    redis.call('PUBLISH', KEYS[3], 'unlocked')
+    return 1
+    "#
+);
+
+/// Distributed read/write lock, like Redisson's RReadWriteLock
+///
+/// Shares the `lock:{name}` hash with `RedissonLock`: a `mode` field ("read" or "write")
+/// plus reentrant hold counts per holder (`{uuid}:{thread_id}` for reads, with a `:write`
+/// suffix for the writer). Each read hold has its own lease, tracked in `lock_readers:{name}`,
+/// and the lock lives as long as its longest lease.
+///
+/// A writer may take read holds and then release the write hold to downgrade; upgrading
+/// from read to write is not supported, as two upgrading readers would deadlock.
+#[derive(Debug, Clone)]
+pub struct RedissonReadWriteLock {
+    lock: RedissonLock,
+}
+
+impl RedissonReadWriteLock {
+    pub fn new(redis_url: &str, lock_name: &str, lease_time_ms: u64) -> RedisResult<Self> {
+        Ok(RedissonReadWriteLock {
+            lock: RedissonLock::new(redis_url, lock_name, lease_time_ms)?,
+        })
+    }
+
+    /// Take a read hold without waiting
+    pub fn try_read_lock(&self) -> RedisResult<bool> {
+        Ok(self.try_acquire_read()?.is_none())
+    }
+
+    /// Take a read hold, waiting as long as a writer holds the lock
+    pub fn read_lock(&self) -> RedisResult<()> {
+        wait_for_release(&self.lock.client, &self.lock.channel_name(), None, || self.try_acquire_read())?;
+        Ok(())
+    }
+
+    /// Take a read hold, waiting up to `wait` for the writer to release
+    pub fn try_read_lock_for(&self, wait: Duration) -> RedisResult<bool> {
+        wait_for_release(&self.lock.client, &self.lock.channel_name(), Some(wait), || self.try_acquire_read())
+    }
+
+    /// Release one read hold of the current thread
+    pub fn unlock_read(&self) -> RedisResult<bool> {
+        let result: Option<i32> = self.release(READ_RELEASE_SCRIPT, self.lock.holder_id())?;
+        Ok(result.is_some())
+    }
+
+    /// Take the write hold without waiting
+    pub fn try_write_lock(&self) -> RedisResult<bool> {
+        Ok(self.try_acquire_write()?.is_none())
+    }
+
+    /// Take the write hold, waiting as long as anyone else holds the lock
+    pub fn write_lock(&self) -> RedisResult<()> {
+        wait_for_release(&self.lock.client, &self.lock.channel_name(), None, || self.try_acquire_write())?;
+        Ok(())
+    }
+
+    /// Take the write hold, waiting up to `wait` for readers and writers to release
+    pub fn try_write_lock_for(&self, wait: Duration) -> RedisResult<bool> {
+        wait_for_release(&self.lock.client, &self.lock.channel_name(), Some(wait), || self.try_acquire_write())
+    }
+
+    /// Release one write hold of the current thread
+    pub fn unlock_write(&self) -> RedisResult<bool> {
+        let result: Option<i32> = self.release(WRITE_RELEASE_SCRIPT, self.writer_id())?;
+        Ok(result.is_some())
+    }
+
+    /// Turn the current thread's write hold into a read hold
+    ///
+    /// One script takes the read hold and releases one write hold, so no other writer can
+    /// get in between and a failure never leaves the thread holding both. Returns false if
+    /// the current thread does not hold the write lock.
+    pub fn downgrade(&self) -> RedisResult<bool> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        let result: Option<i32> = redis::Script::new(DOWNGRADE_SCRIPT)
+            .key(&lock_key)
+            .key(self.readers_name())
+            .key(self.lock.channel_name())
+            .arg(self.lock.effective_lease_ms())
+            .arg(self.lock.holder_id())
+            .arg(self.writer_id())
+            .invoke(&mut conn)?;
+        Ok(result.is_some())
+    }
+
+    /// Number of read holds the current thread has on the lock
+    pub fn get_read_hold_count(&self) -> RedisResult<u32> {
+        self.hold_count(&self.lock.holder_id())
+    }
+
+    /// Number of write holds the current thread has on the lock
+    pub fn get_write_hold_count(&self) -> RedisResult<u32> {
+        self.hold_count(&self.writer_id())
+    }
+
+    pub fn is_write_held_by_current_thread(&self) -> RedisResult<bool> {
+        Ok(self.get_write_hold_count()? > 0)
+    }
+
+    /// Check if anyone holds the lock, for reading or writing
+    pub fn is_locked(&self) -> RedisResult<bool> {
+        self.lock.is_locked()
+    }
+
+    /// Check if a writer holds the lock
+    pub fn is_write_locked(&self) -> RedisResult<bool> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        let mode: Option<String> = conn.hget(&lock_key, "mode")?;
+        Ok(mode.as_deref() == Some("write"))
+    }
+
+    pub fn get_remaining_time_to_live(&self) -> RedisResult<i64> {
+        self.lock.get_remaining_time_to_live()
+    }
+
+    /// Hash field of the current thread's write hold
+    fn writer_id(&self) -> String {
+        format!("{}:write", self.lock.holder_id())
+    }
+
+    fn readers_name(&self) -> String {
+        format!("lock_readers:{}", self.lock.lock_name)
+    }
+
+    fn hold_count(&self, field: &str) -> RedisResult<u32> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        let count: Option<u32> = conn.hget(&lock_key, field)?;
+        Ok(count.unwrap_or(0))
+    }
+
+    fn try_acquire_read(&self) -> RedisResult<Option<i64>> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        redis::Script::new(READ_ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(self.readers_name())
+            .arg(self.lock.effective_lease_ms())
+            .arg(self.lock.holder_id())
+            .arg(self.writer_id())
+            .invoke(&mut conn)
+    }
+
+    fn try_acquire_write(&self) -> RedisResult<Option<i64>> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        redis::Script::new(WRITE_ACQUIRE_SCRIPT)
+            .key(&lock_key)
+            .key(self.readers_name())
+            .arg(self.lock.effective_lease_ms())
+            .arg(self.writer_id())
+            .invoke(&mut conn)
+    }
+
+    fn release(&self, script: &str, field: String) -> RedisResult<Option<i32>> {
+        let mut conn = self.lock.client.get_connection()?;
+        let lock_key = format!("lock:{}", self.lock.lock_name);
+
+        redis::Script::new(script)
+            .key(&lock_key)
+            .key(self.readers_name())
+            .key(self.lock.channel_name())
+            .arg(field)
+            .invoke(&mut conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    /// Many threads can hold the read lock at the same time
+    #[test]
+    #[ignore]
+    fn test_concurrent_readers() {
+        let lock_name = "RW_READERS_LOCK";
+        let num_readers = 5;
+        let (tx, rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Arc::new(Mutex::new(release_rx));
+
+        let handles: Vec<_> = (0..num_readers)
+            .map(|_| {
+                let tx = tx.clone();
+                let release_rx = release_rx.clone();
+                thread::spawn(move || {
+                    let lock = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+                    tx.send(lock.try_read_lock().unwrap()).unwrap();
+                    release_rx.lock().unwrap().recv().unwrap();
+                    assert!(lock.unlock_read().unwrap());
+                })
+            })
+            .collect();
+
+        for _ in 0..num_readers {
+            assert!(rx.recv().unwrap(), "Every reader should get the read lock");
+        }
+
+        // Writers are kept out while readers hold the lock
+        let writer = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!writer.try_write_lock().unwrap());
+        assert!(!writer.is_write_locked().unwrap());
+
+        for _ in 0..num_readers {
+            release_tx.send(()).unwrap();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(!writer.is_locked().unwrap(), "Last reader should delete the lock");
+        assert!(writer.try_write_lock().unwrap());
+        assert!(writer.unlock_write().unwrap());
+    }
+
+    /// A writer excludes readers and other writers, and waiters wake on release
+    #[test]
+    #[ignore]
+    fn test_writer_is_exclusive() {
+        let lock_name = "RW_WRITER_LOCK";
+
+        let writer = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(writer.try_write_lock().unwrap());
+        assert!(writer.try_write_lock().unwrap(), "Write lock is reentrant");
+        assert!(writer.is_write_locked().unwrap());
+
+        let other = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!other.try_read_lock().unwrap());
+        assert!(!other.try_write_lock().unwrap());
+        assert!(!other.unlock_write().unwrap());
+
+        let reader = thread::spawn(move || {
+            let lock = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+            let acquired = lock.try_read_lock_for(Duration::from_secs(5)).unwrap();
+            assert!(lock.unlock_read().unwrap());
+            acquired
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(writer.unlock_write().unwrap());
+        assert!(writer.is_write_locked().unwrap(), "One write hold remains");
+        assert!(writer.unlock_write().unwrap());
+
+        assert!(reader.join().unwrap(), "Reader should get in once the writer is done");
+    }
+
+    /// Each read hold expires on its own lease
+    #[test]
+    #[ignore]
+    fn test_reader_individual_leases() {
+        let lock_name = "RW_LEASE_LOCK";
+
+        let short = RedissonReadWriteLock::new(REDIS_URL, lock_name, 200).unwrap();
+        let long = RedissonReadWriteLock::new(REDIS_URL, lock_name, 5000).unwrap();
+        assert!(short.try_read_lock().unwrap());
+        assert!(long.try_read_lock().unwrap());
+        assert!(long.get_remaining_time_to_live().unwrap() > 200);
+
+        thread::sleep(Duration::from_millis(400));
+
+        // The short lease is gone once any script purges it, the long one still holds
+        let writer = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!writer.try_write_lock().unwrap());
+        assert_eq!(short.get_read_hold_count().unwrap(), 0);
+        assert_eq!(long.get_read_hold_count().unwrap(), 1);
+        assert!(!short.unlock_read().unwrap());
+
+        assert!(long.unlock_read().unwrap());
+        assert!(!writer.is_locked().unwrap());
+    }
+
+    /// A writer can downgrade to a reader without letting another writer in
+    #[test]
+    #[ignore]
+    fn test_write_to_read_downgrade() {
+        let lock_name = "RW_DOWNGRADE_LOCK";
+
+        let lock = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(!lock.downgrade().unwrap(), "Cannot downgrade without the write lock");
+
+        assert!(lock.try_write_lock().unwrap());
+        assert!(lock.downgrade().unwrap());
+        assert!(!lock.is_write_locked().unwrap());
+        assert_eq!(lock.get_read_hold_count().unwrap(), 1);
+        assert_eq!(lock.get_write_hold_count().unwrap(), 0);
+
+        // Now other readers may join, but writers still wait
+        let other = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(other.try_read_lock().unwrap());
+        assert!(!other.try_write_lock().unwrap());
+        assert!(other.unlock_read().unwrap());
+
+        assert!(lock.unlock_read().unwrap());
+        assert!(!lock.is_locked().unwrap());
+    }
+
+    /// Downgrading a reentrant write hold gives up one write hold at a time
+    #[test]
+    #[ignore]
+    fn test_reentrant_downgrade() {
+        let lock_name = "RW_REENTRANT_DOWNGRADE_LOCK";
+
+        let lock = RedissonReadWriteLock::new(REDIS_URL, lock_name, 30000).unwrap();
+        assert!(lock.try_write_lock().unwrap());
+        assert!(lock.try_write_lock().unwrap());
+
+        assert!(lock.downgrade().unwrap());
+        assert!(lock.is_write_locked().unwrap(), "One write hold remains");
+        assert_eq!(lock.get_write_hold_count().unwrap(), 1);
+        assert_eq!(lock.get_read_hold_count().unwrap(), 1);
+
+        assert!(lock.downgrade().unwrap());
+        assert!(!lock.is_write_locked().unwrap());
+        assert_eq!(lock.get_read_hold_count().unwrap(), 2);
+
+        assert!(lock.unlock_read().unwrap());
+        assert!(lock.unlock_read().unwrap());
+        assert!(!lock.is_locked().unwrap());
+    }
+}
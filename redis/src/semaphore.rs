@@ -0,0 +1,292 @@
+use redis::{Client, Commands, RedisResult};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::wait_for_release;
+
+/// Take a permit if fewer than the configured number are out, after returning
+/// expired ones to the pool
+/// KEYS: permit zset, permit count. ARGV: permit id, lease ms
+/// Returns nil on success, or ms until the earliest permit expires
+const SEMAPHORE_ACQUIRE_SCRIPT: &str = r#"
+    local permits_key = KEYS[1]
+    local time = redis.call('TIME')
+    local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+    redis.call('ZREMRANGEBYSCORE', permits_key, '-inf', now_ms)
+
+    local limit = tonumber(redis.call('GET', KEYS[2]) or '0')
+    if redis.call('ZCARD', permits_key) < limit then
+        local expires_at = now_ms + tonumber(ARGV[2])
+        redis.call('ZADD', permits_key, expires_at, ARGV[1])
+        local last = redis.call('ZRANGE', permits_key, -1, -1, 'WITHSCORES')
+        redis.call('PEXPIREAT', permits_key, last[2])
+        return nil
+    end
+
+    -- No permit out that could expire (e.g. permits not set yet): poll every second
+    local first = redis.call('ZRANGE', permits_key, 0, 0, 'WITHSCORES')
+    if #first == 0 then
+        return 1000
+    end
+    return tonumber(first[2]) - now_ms
+"#;
+
+/// Reset the lease of a permit we still hold
+/// KEYS: permit zset. ARGV: permit id, lease ms
+/// Returns 1 if updated, 0 if the permit expired or was released
+const SEMAPHORE_UPDATE_LEASE_SCRIPT: &str = r#"
+    local permits_key = KEYS[1]
+    local time = redis.call('TIME')
+    local now_ms = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+    local score = redis.call('ZSCORE', permits_key, ARGV[1])
+    if score == false or tonumber(score) <= now_ms then
+        return 0
+    end
+    redis.call('ZADD', permits_key, now_ms + tonumber(ARGV[2]), ARGV[1])
+    local last = redis.call('ZRANGE', permits_key, -1, -1, 'WITHSCORES')
+    redis.call('PEXPIREAT', permits_key, last[2])
+    return 1
+"#;
+
+/// Return a permit and wake the waiters
+/// KEYS: permit zset, channel. ARGV: permit id
+/// Returns 1 if released, 0 if we did not hold it
+const SEMAPHORE_RELEASE_SCRIPT: &str = r#"
+    if redis.call('ZREM', KEYS[1], ARGV[1]) == 0 then
+        return 0
+    end
+    redis.call('PUBLISH', KEYS[2], 'released')
+    return 1
+"#;
+
+/// A permit taken from a [`RedissonSemaphore`], identified as "{uuid}:{permit_id}"
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Permit(String);
+
+impl Permit {
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Distributed semaphore with expiring permits, like Redisson's RPermitExpirableSemaphore
+///
+/// The number of permits lives in `semaphore_permits:{name}` so every client agrees on it.
+/// Permits out are a `semaphore:{name}` zset scored by expiry time, so a client that dies
+/// holding a permit returns it to the pool once its lease runs out.
+#[derive(Debug, Clone)]
+pub struct RedissonSemaphore {
+    client: Client,
+    name: String,
+    uuid: String,
+}
+
+impl RedissonSemaphore {
+    pub fn new(redis_url: &str, name: &str) -> RedisResult<Self> {
+        Ok(RedissonSemaphore {
+            client: Client::open(redis_url)?,
+            name: name.to_string(),
+            uuid: Uuid::new_v4().to_string(),
+        })
+    }
+
+    /// Set the number of permits, unless it has already been set
+    pub fn try_set_permits(&self, permits: u32) -> RedisResult<bool> {
+        let mut conn = self.client.get_connection()?;
+        conn.set_nx(self.limit_name(), permits)
+    }
+
+    /// Take a permit without waiting, held for at most `lease`
+    pub fn try_acquire(&self, lease: Duration) -> RedisResult<Option<Permit>> {
+        let permit = self.new_permit();
+        Ok(self.try_acquire_permit(&permit, lease)?.is_none().then_some(permit))
+    }
+
+    /// Take a permit, waiting as long as it takes for one to free up
+    pub fn acquire(&self, lease: Duration) -> RedisResult<Permit> {
+        let permit = self.new_permit();
+        wait_for_release(&self.client, &self.channel_name(), None, || {
+            self.try_acquire_permit(&permit, lease)
+        })?;
+        Ok(permit)
+    }
+
+    /// Take a permit, waiting up to `wait` for one to free up
+    pub fn try_acquire_for(&self, wait: Duration, lease: Duration) -> RedisResult<Option<Permit>> {
+        let permit = self.new_permit();
+        let acquired = wait_for_release(&self.client, &self.channel_name(), Some(wait), || {
+            self.try_acquire_permit(&permit, lease)
+        })?;
+        Ok(acquired.then_some(permit))
+    }
+
+    /// Return a permit to the pool
+    /// Returns false if it had already expired or been released
+    pub fn release(&self, permit: &Permit) -> RedisResult<bool> {
+        let mut conn = self.client.get_connection()?;
+
+        let released: i32 = redis::Script::new(SEMAPHORE_RELEASE_SCRIPT)
+            .key(self.permits_name())
+            .key(self.channel_name())
+            .arg(permit.id())
+            .invoke(&mut conn)?;
+        Ok(released == 1)
+    }
+
+    /// Reset the lease of a permit to `lease` from now
+    /// Returns false if it had already expired or been released
+    pub fn update_lease_time(&self, permit: &Permit, lease: Duration) -> RedisResult<bool> {
+        let mut conn = self.client.get_connection()?;
+
+        let updated: i32 = redis::Script::new(SEMAPHORE_UPDATE_LEASE_SCRIPT)
+            .key(self.permits_name())
+            .arg(permit.id())
+            .arg(lease.as_millis() as u64)
+            .invoke(&mut conn)?;
+        Ok(updated == 1)
+    }
+
+    /// Number of permits that could be taken right now
+    pub fn available_permits(&self) -> RedisResult<u32> {
+        let mut conn = self.client.get_connection()?;
+        let now_ms: u64 = {
+            let (secs, micros): (u64, u64) = redis::cmd("TIME").query(&mut conn)?;
+            secs * 1000 + micros / 1000
+        };
+
+        let limit: Option<u32> = conn.get(self.limit_name())?;
+        let out: u32 = conn.zcount(self.permits_name(), format!("({}", now_ms), "+inf")?;
+        Ok(limit.unwrap_or(0).saturating_sub(out))
+    }
+
+    fn new_permit(&self) -> Permit {
+        Permit(format!("{}:{}", self.uuid, Uuid::new_v4()))
+    }
+
+    fn permits_name(&self) -> String {
+        format!("semaphore:{}", self.name)
+    }
+
+    fn limit_name(&self) -> String {
+        format!("semaphore_permits:{}", self.name)
+    }
+
+    fn channel_name(&self) -> String {
+        format!("semaphore_channel:{}", self.name)
+    }
+
+    fn try_acquire_permit(&self, permit: &Permit, lease: Duration) -> RedisResult<Option<i64>> {
+        let mut conn = self.client.get_connection()?;
+
+        redis::Script::new(SEMAPHORE_ACQUIRE_SCRIPT)
+            .key(self.permits_name())
+            .key(self.limit_name())
+            .arg(permit.id())
+            .arg(lease.as_millis() as u64)
+            .invoke(&mut conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    const REDIS_URL: &str = "redis://127.0.0.1:6379/";
+
+    fn reset(name: &str) {
+        let client = Client::open(REDIS_URL).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let _: () = conn
+            .del(&[format!("semaphore:{}", name), format!("semaphore_permits:{}", name)])
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn test_semaphore_caps_permits() {
+        let name = "SEMAPHORE_CAP";
+        reset(name);
+
+        let semaphore = RedissonSemaphore::new(REDIS_URL, name).unwrap();
+        assert!(semaphore.try_set_permits(2).unwrap());
+        assert!(!semaphore.try_set_permits(5).unwrap(), "Permits are only set once");
+        assert_eq!(semaphore.available_permits().unwrap(), 2);
+
+        let first = semaphore.try_acquire(Duration::from_secs(30)).unwrap().expect("first permit");
+        let second = semaphore.try_acquire(Duration::from_secs(30)).unwrap().expect("second permit");
+        assert_ne!(first, second);
+        assert!(semaphore.try_acquire(Duration::from_secs(30)).unwrap().is_none());
+        assert_eq!(semaphore.available_permits().unwrap(), 0);
+
+        assert!(semaphore.release(&first).unwrap());
+        assert!(!semaphore.release(&first).unwrap(), "A permit is only released once");
+        assert_eq!(semaphore.available_permits().unwrap(), 1);
+
+        assert!(semaphore.release(&second).unwrap());
+        reset(name);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_semaphore_permit_expires() {
+        let name = "SEMAPHORE_EXPIRY";
+        reset(name);
+
+        let semaphore = RedissonSemaphore::new(REDIS_URL, name).unwrap();
+        assert!(semaphore.try_set_permits(1).unwrap());
+
+        let permit = semaphore.try_acquire(Duration::from_millis(200)).unwrap().expect("permit");
+        assert!(semaphore.try_acquire(Duration::from_secs(30)).unwrap().is_none());
+
+        // A holder that never releases gives the permit back when its lease runs out
+        let other = semaphore
+            .try_acquire_for(Duration::from_secs(2), Duration::from_secs(30))
+            .unwrap()
+            .expect("expired permit should return to the pool");
+        assert!(!semaphore.update_lease_time(&permit, Duration::from_secs(30)).unwrap());
+        assert!(!semaphore.release(&permit).unwrap());
+
+        assert!(semaphore.update_lease_time(&other, Duration::from_secs(60)).unwrap());
+        assert!(semaphore.release(&other).unwrap());
+        reset(name);
+    }
+
+    /// No more than the configured number of workers run at once
+    #[test]
+    #[ignore]
+    fn test_semaphore_limits_concurrency() {
+        let name = "SEMAPHORE_CONCURRENCY";
+        let permits = 3;
+        reset(name);
+        RedissonSemaphore::new(REDIS_URL, name).unwrap().try_set_permits(permits).unwrap();
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                thread::spawn(move || {
+                    let semaphore = RedissonSemaphore::new(REDIS_URL, name).unwrap();
+                    let permit = semaphore.acquire(Duration::from_secs(30)).unwrap();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    assert!(semaphore.release(&permit).unwrap());
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(max_running.load(Ordering::SeqCst) <= permits as usize);
+        reset(name);
+    }
+}